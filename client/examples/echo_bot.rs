@@ -0,0 +1,29 @@
+// echo bot: repeats every message of the chat, and answers the private ones.
+//
+// run it with: cargo run --example echo_bot -- <address> <port> [nick]
+
+use chat_client::{Connection, Event};
+
+use std::{env, io, process};
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        println!("usage is: echo_bot <address> <port> [nick]");
+        process::exit(1);
+    }
+    let nick = args.get(3).map_or("echo", String::as_str);
+
+    let mut connection = Connection::connect(format!("{}:{}", args[1], args[2]))?;
+    connection.join(nick)?;
+    let sender = connection.sender();
+
+    for event in connection.events() {
+        match event? {
+            Event::Message { nick, text } => sender.send(&format!("{} said: {}", nick, text))?,
+            Event::PrivateMessage { nick, text } => sender.send_private(&nick, &text)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+// line editing helpers: the input history kept between sessions, and the
+// completion of commands and nicknames with Tab.
+//
+// The nicknames are the ones seen so far, in the WHO answers and in the
+// messages and join notices, so they are completed even without asking WHO.
+
+use chat_client::Event;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::{FileHistory, History};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use std::collections::BTreeSet;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub const MAX_HISTORY: usize = 1000; // lines kept in the history file
+
+// the commands of the server, as they are typed.
+pub const COMMANDS: &[&str] = &[
+    "ANNOUNCE", "AWAY", "BACK", "BAN", "HELP", "JOIN", "KICK", "LEAVE", "MOTD", "MSG", "MUTE",
+    "OPER", "SAY", "UNMUTE", "VERSION", "WHO", "WHOIS",
+];
+
+pub type SharedNicks = Arc<Mutex<BTreeSet<String>>>;
+
+// file where the input history is kept, in the home directory. It can be
+// changed with the CHAT_CLIENT_HISTORY environment variable.
+pub fn history_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("CHAT_CLIENT_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".chat_client_history"))
+}
+
+// the history of the previous sessions. It is empty when there is no
+// history file yet, or it cannot be read.
+pub fn load_history() -> FileHistory {
+    let mut history = FileHistory::new();
+    let _ = history.set_max_len(MAX_HISTORY);
+    if let Some(path) = history_path() {
+        let _ = history.load(&path);
+    }
+    history
+}
+
+// add a line to the history, and to the history file, so it is kept even if
+// the client does not end cleanly. The lines with a password are not kept.
+pub fn remember(history: &mut FileHistory, line: &str) {
+    if has_password(line) {
+        return;
+    }
+    if let Ok(true) = history.add(line) {
+        if let Some(path) = history_path() {
+            let _ = history.append(&path);
+        }
+    }
+}
+
+// i.e.: `OPER s3cret`
+pub fn has_password(line: &str) -> bool {
+    line.split_whitespace().next() == Some("OPER")
+}
+
+// remember the nicknames mentioned by an event.
+pub fn track_nicks(event: &Event, nicks: &SharedNicks) {
+    let mut nicks = nicks.lock().unwrap();
+    match event {
+        Event::Message { nick, .. } | Event::PrivateMessage { nick, .. } | Event::Joined(nick) => {
+            nicks.insert(nick.clone());
+        }
+        Event::Left(nick) => {
+            nicks.remove(nick);
+        }
+        Event::Response { name, lines } if name == "WHO" => {
+            nicks.extend(
+                lines
+                    .iter()
+                    .filter_map(|line| line.split_whitespace().next())
+                    .map(String::from),
+            );
+        }
+        _ => {}
+    }
+}
+
+// the start of the word before the cursor, and the words that complete it:
+// commands for the first word of the line, nicknames for the others.
+pub fn completions<'a>(
+    line: &str,
+    pos: usize,
+    nicks: impl IntoIterator<Item = &'a String>,
+) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(char::is_whitespace)
+        .map_or(0, |space| space + 1);
+    let word = &line[start..pos];
+    let candidates = if line[..start].trim().is_empty() {
+        COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(&word.to_uppercase()))
+            .map(|command| command.to_string())
+            .collect()
+    } else {
+        let word = word.to_lowercase();
+        nicks
+            .into_iter()
+            .filter(|nick| nick.to_lowercase().starts_with(&word))
+            .cloned()
+            .collect()
+    };
+    (start, candidates)
+}
+
+// the longest text all the candidates start with.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix = candidates.first().cloned().unwrap_or_default();
+    for candidate in candidates {
+        while !candidate.starts_with(&prefix) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+// completion for the line editor.
+pub struct ChatHelper {
+    pub nicks: SharedNicks,
+}
+
+impl Completer for ChatHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let (start, mut candidates) = completions(line, pos, self.nicks.lock().unwrap().iter());
+        if let [single] = candidates.as_mut_slice() {
+            single.push(' ');
+        }
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ChatHelper {}
+
+impl Validator for ChatHelper {}
+
+impl Helper for ChatHelper {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_completions() {
+        let nicks: BTreeSet<String> = ["alice", "Albert", "bob"]
+            .iter()
+            .map(|nick| nick.to_string())
+            .collect();
+        assert_eq!(
+            completions("WH", 2, &nicks),
+            (0, vec![String::from("WHO"), String::from("WHOIS")])
+        );
+        assert_eq!(
+            completions("  le", 4, &nicks),
+            (2, vec![String::from("LEAVE")])
+        );
+        assert_eq!(
+            completions("MSG al", 6, &nicks),
+            (4, vec![String::from("Albert"), String::from("alice")])
+        );
+        assert_eq!(
+            completions("hi b", 4, &nicks),
+            (3, vec![String::from("bob")])
+        );
+        assert_eq!(completions("hi ", 3, &nicks).1.len(), 3);
+    }
+
+    #[test]
+    fn verify_track_nicks() {
+        let nicks = SharedNicks::default();
+        track_nicks(&Event::Joined(String::from("carol")), &nicks);
+        let who = Event::Response {
+            name: String::from("WHO"),
+            lines: vec![String::from("alice  active"), String::from("bob  idle")],
+        };
+        track_nicks(&who, &nicks);
+        track_nicks(&Event::Left(String::from("bob")), &nicks);
+        assert_eq!(
+            nicks.lock().unwrap().iter().collect::<Vec<_>>(),
+            ["alice", "carol"]
+        );
+    }
+
+    #[test]
+    fn verify_passwords_are_not_remembered() {
+        assert!(has_password("OPER s3cret"));
+        assert!(!has_password("the OPER command"));
+        let mut history = FileHistory::new();
+        remember(&mut history, " OPER s3cret");
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn verify_common_prefix() {
+        assert_eq!(
+            common_prefix(&[String::from("WHO"), String::from("WHOIS")]),
+            "WHO"
+        );
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,146 @@
+// what the server sends, line by line, understood as events.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    // `[nick] text`: a message to the chat.
+    Message { nick: String, text: String },
+    // `[nick (private)] text`: a message sent only to this client, by MSG.
+    PrivateMessage { nick: String, text: String },
+    // `nick has joined the chat`
+    Joined(String),
+    // `nick has left the chat`
+    Left(String),
+    // `>>> text`: a notice or a reply of the server, without the `>>> `.
+    Notice(String),
+    // the lines between `>>> NAME BEGIN` and `>>> NAME END count`, i.e.: the
+    // answer to WHO or HELP.
+    Response { name: String, lines: Vec<String> },
+    // any other line, i.e.: an announcement.
+    Line(String),
+}
+
+impl Event {
+    // understand a line from the server, without the line break. The lines
+    // of a framed response are put together by the `Connection`.
+    pub fn parse(line: &str) -> Event {
+        if let Some(text) = line.strip_prefix(">>> ") {
+            return Event::Notice(text.to_string());
+        }
+        if let Some(rest) = line.strip_prefix('[') {
+            // the names have no spaces, so the first "] " ends the name
+            if let Some((nick, text)) = rest.split_once("] ") {
+                let text = text.to_string();
+                return match nick.strip_suffix(" (private)") {
+                    Some(nick) => Event::PrivateMessage {
+                        nick: nick.to_string(),
+                        text,
+                    },
+                    None => Event::Message {
+                        nick: nick.to_string(),
+                        text,
+                    },
+                };
+            }
+        }
+        if let Some(nick) = single_word(line.strip_suffix(" has joined the chat")) {
+            return Event::Joined(nick);
+        }
+        if let Some(nick) = single_word(line.strip_suffix(" has left the chat")) {
+            return Event::Left(nick);
+        }
+        Event::Line(line.to_string())
+    }
+}
+
+fn single_word(text: Option<&str>) -> Option<String> {
+    text.filter(|text| !text.is_empty() && !text.contains(char::is_whitespace))
+        .map(String::from)
+}
+
+// the event as the line(s) the server sent.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Message { nick, text } => write!(f, "[{}] {}", nick, text),
+            Event::PrivateMessage { nick, text } => write!(f, "[{} (private)] {}", nick, text),
+            Event::Joined(nick) => write!(f, "{} has joined the chat", nick),
+            Event::Left(nick) => write!(f, "{} has left the chat", nick),
+            Event::Notice(text) => write!(f, ">>> {}", text),
+            Event::Response { name, lines } => {
+                writeln!(f, ">>> {} BEGIN", name)?;
+                for line in lines {
+                    writeln!(f, "{}", line)?;
+                }
+                write!(f, ">>> {} END {}", name, lines.len())
+            }
+            Event::Line(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_parse() {
+        assert_eq!(
+            Event::parse("[bob] hi there"),
+            Event::Message {
+                nick: String::from("bob"),
+                text: String::from("hi there")
+            }
+        );
+        assert_eq!(
+            Event::parse("[bob (private)] psst"),
+            Event::PrivateMessage {
+                nick: String::from("bob"),
+                text: String::from("psst")
+            }
+        );
+        assert_eq!(
+            Event::parse("bob has joined the chat"),
+            Event::Joined(String::from("bob"))
+        );
+        assert_eq!(
+            Event::parse("bob has left the chat"),
+            Event::Left(String::from("bob"))
+        );
+        assert_eq!(
+            Event::parse(">>> welcome to the chat, bob!"),
+            Event::Notice(String::from("welcome to the chat, bob!"))
+        );
+        assert_eq!(
+            Event::parse("*** ANNOUNCEMENT: maintenance at 5"),
+            Event::Line(String::from("*** ANNOUNCEMENT: maintenance at 5"))
+        );
+        assert_eq!(
+            Event::parse("a b has left the chat"),
+            Event::Line(String::from("a b has left the chat"))
+        );
+    }
+
+    #[test]
+    fn verify_display_gives_the_line_back() {
+        for line in [
+            "[bob] hi",
+            "[bob (private)] psst",
+            "bob has joined the chat",
+            ">>> you are the only user here",
+            "*** ANNOUNCEMENT: hello",
+        ] {
+            assert_eq!(Event::parse(line).to_string(), line);
+        }
+        let response = Event::Response {
+            name: String::from("WHO"),
+            lines: vec![String::from("alice"), String::from("bob")],
+        };
+        assert_eq!(
+            response.to_string(),
+            ">>> WHO BEGIN\nalice\nbob\n>>> WHO END 2"
+        );
+    }
+}
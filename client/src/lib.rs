@@ -0,0 +1,272 @@
+// chat client library, for the `client` binary and for bots.
+//
+// A `Connection` talks the line protocol of the chat server, and turns what
+// the server sends into events, i.e. an echo bot:
+//
+//   let mut connection = Connection::connect("127.0.0.1:1234")?;
+//   connection.join("echo")?;
+//   let sender = connection.sender();
+//   for event in connection.events() {
+//       if let Event::Message { nick, text } = event? {
+//           sender.send(&format!("{} said: {}", nick, text))?;
+//       }
+//   }
+//
+// The `Sender` can be cloned and used from other threads, while a single one
+// reads the events. See examples/echo_bot.rs.
+//
+// `Connection::connect_tls` connects with TLS instead, i.e.: to a server
+// behind a TLS proxy.
+
+mod event;
+mod tls;
+
+pub use crate::event::Event;
+// the formatting of the server log, shared by the transcripts and the scripts
+pub use server::aux::json_string;
+pub use server::logger::format_timestamp;
+// to configure the TLS of `Connection::connect_tls_with`
+pub use rustls;
+
+use crate::tls::{Stream, TlsStream};
+
+use rustls::ClientConfig;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// a connection to the chat server.
+pub struct Connection {
+    sender: Sender,
+    reader: BufReader<Stream>,
+    pending: VecDeque<Event>, // events read while waiting for something else
+}
+
+impl Connection {
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Connection> {
+        Connection::over(Stream::Plain(TcpStream::connect(address)?))
+    }
+
+    // connect with TLS, checking the certificate of the server against the
+    // usual certificate authorities. The certificate must be valid for the
+    // host of the address, i.e.: chat.example.com:1153
+    pub fn connect_tls(address: &str) -> io::Result<Connection> {
+        Connection::connect_tls_with(address, tls::host_of(address), tls::default_config())
+    }
+
+    // connect with TLS, with a configuration of its own, i.e.: to trust a
+    // certificate authority that is not one of the usual ones.
+    pub fn connect_tls_with(
+        address: impl ToSocketAddrs,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<Connection> {
+        let tcp = TcpStream::connect(address)?;
+        let stream = TlsStream::handshake(tcp, server_name, config)?;
+        Connection::over(Stream::Tls(stream))
+    }
+
+    fn over(stream: Stream) -> io::Result<Connection> {
+        Ok(Connection {
+            sender: Sender {
+                stream: Arc::new(Mutex::new(stream.try_clone()?)),
+            },
+            reader: BufReader::new(stream),
+            pending: VecDeque::new(),
+        })
+    }
+
+    // a handle to send to the server, that can be used from other threads.
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    // JOIN the chat, and wait for the server to accept the name. It fails
+    // with the reason of the server if the name is not accepted. Some refusals
+    // have no answer, i.e.: a second JOIN, so a HELP is sent after the JOIN:
+    // the lines are handled in order, and its answer tells when the server is
+    // done with the JOIN.
+    pub fn join(&mut self, nick: &str) -> io::Result<()> {
+        self.sender.send_line(&format!("JOIN {}", nick))?;
+        self.sender.send_line("HELP")?;
+        let mut joined = Err(io::Error::other("the name was not accepted"));
+        loop {
+            let event = match self.read_event()? {
+                Some(event) => event,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            match &event {
+                Event::Response { name, .. } if name == "HELP" => return joined,
+                Event::Notice(text) if text.starts_with("welcome to the chat") => joined = Ok(()),
+                Event::Notice(text)
+                    if text.starts_with("invalid name") || text.starts_with("the name ") =>
+                {
+                    joined = Err(io::Error::other(text.clone()))
+                }
+                _ => self.pending.push_back(event),
+            }
+        }
+    }
+
+    // send a message to the chat.
+    pub fn send(&self, text: &str) -> io::Result<()> {
+        self.sender.send(text)
+    }
+
+    // send a message to the chat, and wait until the server has handled it.
+    // It fails with the reason of the server if the message is refused, i.e.:
+    // the user has not joined, is muted or too fast, or a hook vetoed it. The
+    // message goes with SAY, which the server always answers.
+    pub fn send_confirmed(&mut self, text: &str) -> io::Result<()> {
+        self.sender.send_line(&format!("SAY {}", text))?;
+        loop {
+            match self.read_event()? {
+                Some(Event::Notice(text)) if text == "SAY OK" => return Ok(()),
+                Some(Event::Notice(text)) if text.starts_with("SAY REFUSED ") => {
+                    return Err(io::Error::other(&text["SAY REFUSED ".len()..]))
+                }
+                Some(event) => self.pending.push_back(event),
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            }
+        }
+    }
+
+    // send a message to a single user.
+    pub fn send_private(&self, nick: &str, text: &str) -> io::Result<()> {
+        self.sender.send_private(nick, text)
+    }
+
+    // the next event, or None once the server closes the connection.
+    pub fn next_event(&mut self) -> io::Result<Option<Event>> {
+        match self.pending.pop_front() {
+            Some(event) => Ok(Some(event)),
+            None => self.read_event(),
+        }
+    }
+
+    // how long to wait for the next event, before failing with a timeout.
+    // None waits forever, the default.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.reader.get_ref().set_read_timeout(timeout)
+    }
+
+    // the events, until the server closes the connection.
+    pub fn events(&mut self) -> Events<'_> {
+        Events { connection: self }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&line);
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+
+            // answer the keep alive checks, so the server keeps the connection
+            if line == "PING" || line.starts_with("PING ") {
+                self.sender.send_line(&format!("PONG{}", &line[4..]))?;
+                continue;
+            }
+
+            // i.e.: >>> WHO BEGIN
+            if let Some(name) = response_name(&line) {
+                let end = format!(">>> {} END ", name);
+                let mut lines = Vec::new();
+                loop {
+                    match self.read_line()? {
+                        Some(line) if line.starts_with(&end) => break,
+                        Some(line) => lines.push(line),
+                        None => return Ok(None),
+                    }
+                }
+                return Ok(Some(Event::Response { name, lines }));
+            }
+
+            return Ok(Some(Event::parse(&line)));
+        }
+    }
+}
+
+// the name of a framed response, if the line starts one.
+fn response_name(line: &str) -> Option<String> {
+    let name = line.strip_prefix(">>> ")?.strip_suffix(" BEGIN")?;
+    let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase());
+    is_name.then(|| name.to_string())
+}
+
+// iterator over the events of a connection.
+pub struct Events<'a> {
+    connection: &'a mut Connection,
+}
+
+impl Iterator for Events<'_> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        self.connection.next_event().transpose()
+    }
+}
+
+// the sending side of a connection.
+#[derive(Clone)]
+pub struct Sender {
+    stream: Arc<Mutex<Stream>>,
+}
+
+impl Sender {
+    // send a line of the protocol as it is, i.e.: `WHO a*`
+    pub fn send_line(&self, line: &str) -> io::Result<()> {
+        // a line break would make the rest of the text another line
+        let line = line.replace(['\r', '\n'], " ");
+        self.stream
+            .lock()
+            .unwrap()
+            .write_all(format!("{}\n", line).as_bytes())
+    }
+
+    // send a message to the chat. A text starting with the name of a command
+    // is taken as that command by the server.
+    pub fn send(&self, text: &str) -> io::Result<()> {
+        self.send_line(text)
+    }
+
+    pub fn send_private(&self, nick: &str, text: &str) -> io::Result<()> {
+        self.send_line(&format!("MSG {} {}", nick, text))
+    }
+
+    // LEAVE the chat. The server closes the connection afterwards.
+    pub fn leave(&self) -> io::Result<()> {
+        self.send_line("LEAVE")
+    }
+
+    // close the connection, which also ends the events.
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.stream.lock().unwrap().shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_response_name() {
+        assert_eq!(response_name(">>> WHO BEGIN"), Some(String::from("WHO")));
+        assert_eq!(response_name(">>> HELP BEGIN"), Some(String::from("HELP")));
+        assert_eq!(response_name(">>> let us BEGIN"), None);
+        assert_eq!(response_name("[bob] WHO BEGIN"), None);
+    }
+}
@@ -6,72 +6,445 @@
 // reference: https://github.com/PacktPublishing/Network-Programming-with-Rust/blob/master/Chapter03/tcp-client-timeout.rs
 // https://stevedonovan.github.io/rust-gentle-intro/7-shared-and-networking.html#a-better-way-to-resolve-addresses
 
-use std::net::{TcpStream};
-use std::str;
-use std::io::{self, BufRead, BufReader, Write};
-use std::{env, process};
+mod editing;
+mod options;
+mod script;
+mod transcript;
+mod tui;
+
+use chat_client::{Connection, Event, Sender};
+
+use editing::{ChatHelper, SharedNicks};
+use options::{Mode, Options};
+use transcript::Transcript;
+
+use rustyline::history::FileHistory;
+use rustyline::{Editor, ExternalPrinter};
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use std::{env, process};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_PENDING_LINES: usize = 100; // lines typed while offline that are kept to be re-sent
+
+// state of the chat session, shared between the stdin loop and the thread
+// reading from the server, so it survives a reconnection.
+struct Session {
+    stream: Option<Sender>,        // None while the client is offline
+    join_name: Option<String>,     // name used in the last JOIN, re-sent after reconnecting
+    pending: VecDeque<String>,     // lines typed while offline
+    leaving: bool,                 // LEAVE was sent, so the server closing the socket is expected
+    nick: Option<String>,          // name given with --nick, used by a JOIN without name
+    reconnect: bool,               // reconnect when the connection drops, or end the client
+    tls: bool,                     // connect with TLS, also when reconnecting
+    transcript: Option<Transcript>,
+}
+
+impl Session {
+    fn received(&mut self, event: &Event) {
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.received(event);
+        }
+    }
+}
+
+type SharedSession = Arc<Mutex<Session>>;
+
+type ChatEditor = Editor<ChatHelper, FileHistory>;
+
+// where the lines for the user go. While the line editor waits for input,
+// they are printed through it, so they do not mess up the line being typed.
+#[derive(Clone, Default)]
+struct Output {
+    printer: Arc<Mutex<Option<Box<dyn ExternalPrinter + Send>>>>,
+}
+
+impl Output {
+    fn say(&self, text: &str) {
+        match self.printer.lock().unwrap().as_mut() {
+            Some(printer) => {
+                let _ = printer.print(text.to_string());
+            }
+            None => println!("{}", text),
+        }
+    }
+
+    fn is_editing(&self) -> bool {
+        self.printer.lock().unwrap().is_some()
+    }
+}
+
+// the text of the profiles file.
+fn read_profiles() -> Result<String, String> {
+    let path = options::profiles_path().ok_or("there is no home directory for the profiles")?;
+    fs::read_to_string(&path).map_err(|error| format!("cannot read {}: {}", path.display(), error))
+}
 
 fn main() {
-    let args : Vec<String> = env::args().collect();
+    let args : Vec<String> = env::args().skip(1).collect();
+
+    let options = Options::parse(&args, read_profiles).unwrap_or_else(|error| {
+        eprintln!(">>> {}", error);
+        eprintln!("{}", options::USAGE);
+        process::exit(script::EXIT_USAGE);
+    });
 
-    if args.len() > 4 || args.len() < 3 {
-        println!("usage is: ./tcp_client <address> <port>");
-        process::exit(1);
+    match &options.mode {
+        Mode::Chat => {}
+        Mode::Send(text) => process::exit(script::send(&options, text)),
+        Mode::Listen => process::exit(script::listen(&options)),
     }
 
-    let address = &args[1];
-    let port = &args[2];
+    let server_address = options.server_address.clone();
+    println!("server: {}", server_address);
 
-    println!("address: {}", address);
-    println!("port: {}", port);
+    let transcript = options.log.as_ref().map(|directory| {
+        Transcript::open(directory, &server_address, options.log_format).unwrap_or_else(|error| {
+            println!(">>> cannot open the transcript in {}: {}", directory.display(), error);
+            process::exit(1);
+        })
+    });
 
-    let stream = TcpStream::connect(address.to_owned() + ":" + port).expect("Could not connect to the server");
+    // the server may not be up yet: wait for it, as after a drop
+    let connection = if options.reconnect {
+        connect_with_backoff(&server_address, options.tls, &Output::default())
+    } else {
+        connect(&server_address, options.tls).unwrap_or_else(|error| {
+            println!(">>> could not connect to {}: {}", server_address, error);
+            process::exit(1);
+        })
+    };
 
-    //let mut input = String::new();
-    //let mut buffer : Vec<u8> = Vec::new();
+    let session: SharedSession = Arc::new(Mutex::new(Session {
+        stream: Some(connection.sender()),
+        join_name: None,
+        pending: VecDeque::new(),
+        leaving: false,
+        nick: options.nick.clone(),
+        reconnect: options.reconnect,
+        tls: options.tls,
+        transcript,
+    }));
 
-    //let server_stream= stream.try_clone().expect("failure trying to clone a stream");
+    if options.auto_join {
+        send_or_queue("JOIN", &session);
+    }
 
-    handle_feedback(&stream);
+    if options.tui {
+        if let Err(error) = tui::run(&server_address, connection, &session) {
+            println!("terminal error: {}", error);
+            process::exit(1);
+        }
+        let session = session.lock().unwrap();
+        if session.stream.is_none() && !session.leaving {
+            println!(">>> connection to the server lost");
+            process::exit(1);
+        }
+        return;
+    }
 
-    //loop {                // loop to read from the input and send to the server.
-    //    input.clear();
-    //    buffer.clear();
-    //    io::stdin().read_line(&mut input).expect("Failed to read from stdin");
-    //    stream.write(input.as_bytes()).expect("Failed to write to server");
+    let output = Output::default();
+    let nicks = SharedNicks::default();
 
-    //    let mut reader = BufReader::new(&stream);
+    // the line editor is only for a terminal, input from a pipe or a file is
+    // read as it is
+    let editor = if io::stdin().is_terminal() {
+        line_editor(&output, &nicks)
+    } else {
+        None
+    };
 
-    //    reader.read_until(b'\n', &mut buffer).expect("Could not read into buffer");
-    //    print!("{}", str::from_utf8(&buffer).expect("Could not write buffer as string"));
-    //}
-    //
+    handle_feedback(connection, &server_address, &session, &output, &nicks);
 
-    read_text_and_send_to_server(&stream);
+    match editor {
+        Some(editor) => edit_text_and_send_to_server(editor, &session, &output),
+        None => read_text_and_send_to_server(&session, &output),
+    }
 }
 
-fn handle_feedback(inputstream: &TcpStream) {
-    let server_stream= inputstream.try_clone().expect("failure trying to clone a stream");
+// spawn the thread that prints what the server sends. When the connection
+// drops, this thread is also the one in charge of reconnecting.
+fn handle_feedback(connection: Connection, server_address: &str, session: &SharedSession,
+                   output: &Output, nicks: &SharedNicks) {
+    let server_address = server_address.to_owned();
+    let session = Arc::clone(session);
+    let output = output.clone();
+    let nicks = Arc::clone(nicks);
     thread::spawn(move || {
-        println!("server reading loop");
+        let mut connection = connection;
+        loop {
+            print_server_messages(&mut connection, &session, &output, &nicks);
+
+            {
+                let mut session = session.lock().unwrap();
+                session.stream = None;
+                if session.leaving {
+                    output.say(">>> you have left the chat");
+                    process::exit(0);
+                }
+                if !session.reconnect {
+                    if !output.is_editing() {
+                        output.say(">>> connection to the server lost");
+                        process::exit(1);
+                    }
+                    // the line editor ends the client, with the terminal as it was
+                    output.say(">>> connection to the server lost, press Enter to quit");
+                    return;
+                }
+            }
+
+            output.say(">>> connection to the server lost, reconnecting...");
+            let tls = session.lock().unwrap().tls;
+            connection = connect_with_backoff(&server_address, tls, &output);
+            output.say(&format!(">>> reconnected to {}", server_address));
+            resume_session(connection.sender(), &session);
+        }
     });
 }
 
-fn read_text_and_send_to_server(mut stream: &TcpStream) {
+// print everything received from the server, until the connection is
+// closed or fails.
+fn print_server_messages(connection: &mut Connection, session: &SharedSession,
+                         output: &Output, nicks: &SharedNicks) {
+    for event in connection.events() {
+        match event {
+            Ok(event) => {
+                session.lock().unwrap().received(&event);
+                editing::track_nicks(&event, nicks);
+                output.say(&event.to_string());
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+// connect with TLS or plain TCP, as chosen with --tls.
+fn connect(server_address: &str, tls: bool) -> io::Result<Connection> {
+    if tls {
+        Connection::connect_tls(server_address)
+    } else {
+        Connection::connect(server_address)
+    }
+}
+
+// try to connect until it works, doubling the wait after every failure.
+fn connect_with_backoff(server_address: &str, tls: bool, output: &Output) -> Connection {
+    let mut delay = INITIAL_BACKOFF;
+    loop {
+        match connect(server_address, tls) {
+            Ok(connection) => return connection,
+            Err(error) => {
+                output.say(&format!(">>> could not connect to {}: {} (retrying in {:?})", server_address, error, delay));
+                thread::sleep(delay);
+                delay = next_backoff(delay);
+            }
+        }
+    }
+}
+
+fn next_backoff(delay: Duration) -> Duration {
+    (delay * 2).min(MAX_BACKOFF)
+}
+
+// after reconnecting, join again with the previous name and send the lines
+// that were typed while offline.
+fn resume_session(writer: Sender, session: &SharedSession) {
+    let mut session = session.lock().unwrap();
+
+    if let Some(name) = &session.join_name {
+        if writer.send_line(&format!("JOIN {}", name)).is_err() {
+            return; // the reading loop will notice the connection is gone again
+        }
+    }
+
+    while let Some(line) = session.pending.pop_front() {
+        if writer.send_line(&line).is_err() {
+            session.pending.push_front(line);
+            return;
+        }
+    }
+
+    session.stream = Some(writer);
+}
+
+// the line editor, with the history of the previous sessions and the
+// completion of commands and nicknames. None if the terminal does not allow it.
+fn line_editor(output: &Output, nicks: &SharedNicks) -> Option<ChatEditor> {
+    let mut editor = ChatEditor::with_history(Default::default(), editing::load_history()).ok()?;
+    editor.set_helper(Some(ChatHelper { nicks: Arc::clone(nicks) }));
+    let printer = editor.create_external_printer().ok()?;
+    *output.printer.lock().unwrap() = Some(Box::new(printer));
+    Some(editor)
+}
 
-    let server_stream= stream.try_clone().expect("failure trying to clone a stream");
-    let mut input = String::new();
-    let mut buffer : Vec<u8> = Vec::new();
+fn edit_text_and_send_to_server(mut editor: ChatEditor, session: &SharedSession, output: &Output) {
+    loop {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            Err(_) => return, // Ctrl-C, Ctrl-D or a terminal error
+        };
+        {
+            let session = session.lock().unwrap();
+            if session.stream.is_none() && !session.reconnect {
+                process::exit(1); // the connection was lost
+            }
+        }
+        editing::remember(editor.history_mut(), &input);
+        send_input(&input, session, output);
+
+        if session.lock().unwrap().leaving {
+            // the server closes the connection, and the reading thread ends
+            // the client. The terminal is left as it was, not waiting for input.
+            output.printer.lock().unwrap().take();
+            loop {
+                thread::park();
+            }
+        }
+    }
+}
+
+fn read_text_and_send_to_server(session: &SharedSession, output: &Output) {
+    let mut line: Vec<u8> = Vec::new();
 
     loop {                // loop to read from the input and send to the server.
-        input.clear();
-        buffer.clear();
-        io::stdin().read_line(&mut input).expect("Failed to read from stdin");
-        stream.write(input.as_bytes()).expect("Failed to write to server");
-
-        //let mut reader = BufReader::new(&server_stream);
-        //reader.read_until(b'\n', &mut buffer).expect("Could not read into buffer");
-        //print!("{}", str::from_utf8(&buffer).expect("Could not write buffer as string"));
+        line.clear();
+        let size = io::stdin().lock().read_until(b'\n', &mut line).expect("Failed to read from stdin");
+        if size == 0 {
+            return; // end of input
+        }
+        // invalid UTF-8 (i.e.: a terminal in another encoding) is replaced, not fatal
+        let input = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+
+        send_input(&input, session, output);
+    }
+}
+
+fn send_input(input: &str, session: &SharedSession, output: &Output) {
+    if let Some(pending) = send_or_queue(input, session) {
+        output.say(&format!(">>> offline: message queued ({} pending)", pending));
+    }
+}
+
+// send a line to the server. If the client is offline, the line is queued
+// to be sent after reconnecting, and the number of queued lines is returned.
+fn send_or_queue(input: &str, session: &SharedSession) -> Option<usize> {
+    let mut session = session.lock().unwrap();
+    let input = &with_nick(input, &session);
+    track_join_and_leave(input, &mut session);
+    if let Some(transcript) = session.transcript.as_mut() {
+        transcript.sent(input);
+    }
+
+    let sent = match session.stream.as_mut() {
+        Some(stream) => stream.send_line(input).is_ok(),
+        None => false,
+    };
+
+    if sent {
+        return None;
+    }
+    if let Some(stream) = session.stream.take() {
+        // wake up the reading thread, so it starts reconnecting
+        let _ = stream.shutdown();
+    }
+    if is_join_with_name(input) {
+        return Some(session.pending.len()); // resume_session sends it, as the last JOIN
+    }
+    if session.pending.len() == MAX_PENDING_LINES {
+        session.pending.pop_front();
+    }
+    session.pending.push_back(input.to_string());
+    Some(session.pending.len())
+}
+
+// a JOIN without name uses the nick given with --nick.
+fn with_nick(input: &str, session: &Session) -> String {
+    match &session.nick {
+        Some(nick) if input.trim() == "JOIN" => format!("JOIN {}", nick),
+        _ => input.to_string(),
+    }
+}
+
+fn is_join_with_name(input: &str) -> bool {
+    let mut words = input.split_ascii_whitespace();
+    words.next() == Some("JOIN") && words.next().is_some()
+}
+
+// remember the name given in JOIN, so it can be re-sent after a reconnection.
+fn track_join_and_leave(input: &str, session: &mut Session) {
+    let mut words = input.split_ascii_whitespace();
+    match words.next() {
+        Some("JOIN") => {
+            if let Some(name) = words.next() {
+                session.join_name = Some(name.to_string());
+            }
+        }
+        Some("LEAVE") => {
+            session.join_name = None;
+            session.leaving = true;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn empty_session() -> Session {
+        Session {
+            stream: None,
+            join_name: None,
+            pending: VecDeque::new(),
+            leaving: false,
+            nick: None,
+            reconnect: true,
+            tls: false,
+            transcript: None,
+        }
+    }
+
+    #[test]
+    fn verify_backoff_doubles_until_max() {
+        assert_eq!(next_backoff(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(next_backoff(MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn verify_join_name_is_tracked() {
+        let mut session = empty_session();
+        track_join_and_leave("  JOIN Alice\n", &mut session);
+        assert_eq!(session.join_name, Some(String::from("Alice")));
+        track_join_and_leave("hello\n", &mut session);
+        assert_eq!(session.join_name, Some(String::from("Alice")));
+        track_join_and_leave("LEAVE\n", &mut session);
+        assert_eq!(session.join_name, None);
+        assert!(session.leaving);
+    }
+
+    #[test]
+    fn verify_join_is_not_queued_twice() {
+        let session: SharedSession = Arc::new(Mutex::new(empty_session()));
+        assert_eq!(send_or_queue("hello", &session), Some(1));
+        assert_eq!(send_or_queue("JOIN alice", &session), Some(1));
+        let session = session.lock().unwrap();
+        assert_eq!(session.join_name, Some(String::from("alice")));
+        assert_eq!(session.pending, ["hello"]);
+    }
+
+    #[test]
+    fn verify_join_uses_the_nick() {
+        let mut session = empty_session();
+        assert_eq!(with_nick("JOIN", &session), "JOIN");
+        session.nick = Some(String::from("alice"));
+        assert_eq!(with_nick(" JOIN ", &session), "JOIN alice");
+        assert_eq!(with_nick("JOIN bob", &session), "JOIN bob");
     }
 }
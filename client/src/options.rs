@@ -0,0 +1,361 @@
+// command line options, and the profiles of the servers used often.
+//
+// The client is started with the address and the port of a server, or with
+// the name of a profile, i.e.:
+//
+//   client 127.0.0.1 1153 --nick alice --auto-join
+//   client work
+//
+// or to be used from scripts, to send a message or to print the ones of the
+// chat as JSON lines (see script.rs):
+//
+//   client send --nick ci-bot 127.0.0.1 1153 "deploy done"
+//   client listen work
+//
+// The profiles are kept in `~/.chat_client_profiles` (or the file given by
+// CHAT_CLIENT_PROFILES), one section per server with `key = value` settings
+// like the configuration of the server, i.e.:
+//
+//   # the chat of the office
+//   [work]
+//   address = chat.example.com
+//   port = 1153
+//   nick = alice
+//   log = /home/alice/chat-logs
+//
+// A profile with a nick joins automatically. The options given in the command
+// line take precedence over the ones of the profile.
+
+use crate::transcript::Format;
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "usage is: ./tcp_client <address> <port> [options]
+          ./tcp_client <profile> [options]
+          ./tcp_client send --nick <name> (<address> <port> | <profile>) <message>
+          ./tcp_client listen [--nick <name>] (<address> <port> | <profile>)
+options:
+  --nick <name>     name used to JOIN the chat
+  --auto-join       JOIN with the nick when connected (the default in a profile with a nick)
+  --tls             connect with TLS
+  --reconnect       reconnect when the connection drops (the default)
+  --no-reconnect    end when the connection drops
+  --log <dir>       keep a transcript of the chat in this directory
+  --log-format <f>  text (the default) or json, for JSON lines
+  --tui             full screen interface";
+
+pub const LISTEN_NICK: &str = "listener"; // start of the nick of `listen` without --nick
+
+// what the client does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    Chat,         // the interactive chat
+    Send(String), // send this message and end
+    Listen,       // print the messages of the chat
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    pub server_address: String, // i.e.: 127.0.0.1:1153
+    pub nick: Option<String>,
+    pub auto_join: bool,
+    pub tls: bool,
+    pub reconnect: bool,
+    pub log: Option<PathBuf>,
+    pub log_format: Format,
+    pub tui: bool,
+}
+
+// the settings of a profile, all of them optional.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub nick: Option<String>,
+    pub auto_join: Option<bool>,
+    pub tls: Option<bool>,
+    pub reconnect: Option<bool>,
+    pub log: Option<PathBuf>,
+    pub log_format: Option<Format>,
+    pub tui: Option<bool>,
+}
+
+// nick of `listen` without --nick, with a random end so several listeners
+// can join at once, i.e.: listener-3fa2c1
+fn listen_nick() -> String {
+    let random = RandomState::new().build_hasher().finish();
+    format!("{}-{:06x}", LISTEN_NICK, random & 0xFF_FFFF)
+}
+
+// file where the profiles are kept, in the home directory.
+pub fn profiles_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("CHAT_CLIENT_PROFILES") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".chat_client_profiles"))
+}
+
+impl Options {
+    // the options of the command line, without the name of the program. The
+    // text of the profiles file is only needed when a profile is used.
+    pub fn parse(
+        args: &[String],
+        profiles: impl FnOnce() -> Result<String, String>,
+    ) -> Result<Options, String> {
+        let mut positional = Vec::new();
+        let mut flags = Profile::default();
+
+        let mut args = args.iter().peekable();
+        let mut mode = match args.peek().map(|arg| arg.as_str()) {
+            Some("send") => Mode::Send(String::new()),
+            Some("listen") => Mode::Listen,
+            _ => Mode::Chat,
+        };
+        if mode != Mode::Chat {
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--nick" => flags.nick = Some(flag_value(arg, args.next())?.to_string()),
+                "--auto-join" => flags.auto_join = Some(true),
+                "--tls" => flags.tls = Some(true),
+                "--reconnect" => flags.reconnect = Some(true),
+                "--no-reconnect" => flags.reconnect = Some(false),
+                "--log" => flags.log = Some(PathBuf::from(flag_value(arg, args.next())?)),
+                "--log-format" => {
+                    flags.log_format = Some(parse_setting(
+                        "--log-format",
+                        flag_value(arg, args.next())?,
+                    )?)
+                }
+                "--tui" => flags.tui = Some(true),
+                flag if flag.starts_with("--") => return Err(format!("unknown option `{}`", flag)),
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        if let Mode::Send(message) = &mut mode {
+            *message = positional
+                .pop()
+                .ok_or("expected the message to send")?
+                .to_string();
+        }
+
+        let profile = match positional[..] {
+            [address, port] => Profile {
+                address: Some(address.to_string()),
+                port: Some(parse_setting("port", port)?),
+                ..Profile::default()
+            },
+            [name] => {
+                let mut profiles = parse_profiles(&profiles()?)?;
+                let profile = profiles
+                    .remove(name)
+                    .ok_or(format!("there is no profile named `{}`", name))?;
+                Profile {
+                    // a profile with a nick joins unless it says otherwise
+                    auto_join: profile.auto_join.or(Some(profile.nick.is_some())),
+                    ..profile
+                }
+            }
+            _ => return Err(String::from("expected an address and a port, or a profile")),
+        };
+
+        let (address, port) = match (profile.address, profile.port) {
+            (Some(address), Some(port)) => (address, port),
+            _ => return Err(String::from("the profile needs an address and a port")),
+        };
+        let mut nick = flags.nick.or(profile.nick);
+        let mut auto_join = flags.auto_join.or(profile.auto_join).unwrap_or(false);
+        match mode {
+            Mode::Chat => {}
+            Mode::Send(_) if nick.is_none() => return Err(String::from("send needs a nick")),
+            Mode::Send(_) => auto_join = true,
+            Mode::Listen => {
+                nick.get_or_insert_with(listen_nick);
+                auto_join = true;
+            }
+        }
+        if auto_join && nick.is_none() {
+            return Err(String::from("--auto-join needs a nick"));
+        }
+
+        Ok(Options {
+            mode,
+            server_address: format!("{}:{}", address, port),
+            nick,
+            auto_join,
+            tls: flags.tls.or(profile.tls).unwrap_or(false),
+            reconnect: flags.reconnect.or(profile.reconnect).unwrap_or(true),
+            log: flags.log.or(profile.log),
+            log_format: flags.log_format.or(profile.log_format).unwrap_or_default(),
+            tui: flags.tui.or(profile.tui).unwrap_or(false),
+        })
+    }
+}
+
+fn flag_value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    match value {
+        Some(value) if !value.starts_with("--") => Ok(value),
+        _ => Err(format!("`{}` needs a value", flag)),
+    }
+}
+
+fn parse_setting<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, key))
+}
+
+// the profiles in the text of the profiles file, by name.
+pub fn parse_profiles(text: &str) -> Result<BTreeMap<String, Profile>, String> {
+    let mut profiles = BTreeMap::new();
+    let mut current: Option<&mut Profile> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |error: String| format!("line {}: {}", number + 1, error);
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current = Some(profiles.entry(name.trim().to_string()).or_default());
+            continue;
+        }
+        let profile = current
+            .as_deref_mut()
+            .ok_or_else(|| error(String::from("expected `[profile]` before the settings")))?;
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(error(String::from("expected `key = value`"))),
+        };
+        match key {
+            "address" => profile.address = Some(value.to_string()),
+            "port" => profile.port = Some(parse_setting(key, value).map_err(error)?),
+            "nick" => profile.nick = Some(value.to_string()),
+            "auto_join" => profile.auto_join = Some(parse_setting(key, value).map_err(error)?),
+            "tls" => profile.tls = Some(parse_setting(key, value).map_err(error)?),
+            "reconnect" => profile.reconnect = Some(parse_setting(key, value).map_err(error)?),
+            "log" => profile.log = Some(PathBuf::from(value)),
+            "log_format" => profile.log_format = Some(parse_setting(key, value).map_err(error)?),
+            "tui" => profile.tui = Some(parse_setting(key, value).map_err(error)?),
+            _ => return Err(error(format!("unknown setting `{}`", key))),
+        }
+    }
+
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const PROFILES: &str = "
+# the chat of the office
+[work]
+address = chat.example.com
+port = 1153
+nick = alice
+tls = true
+
+[home]
+address = 127.0.0.1
+port = 1234
+nick = bob
+auto_join = false
+tui = true
+";
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Options::parse(&args, || Ok(PROFILES.to_string()))
+    }
+
+    #[test]
+    fn verify_address_and_flags() {
+        let options = parse(
+            "127.0.0.1 1153 --nick carol --auto-join --no-reconnect --log logs --log-format json",
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                mode: Mode::Chat,
+                server_address: String::from("127.0.0.1:1153"),
+                nick: Some(String::from("carol")),
+                auto_join: true,
+                tls: false,
+                reconnect: false,
+                log: Some(PathBuf::from("logs")),
+                log_format: Format::Json,
+                tui: false,
+            }
+        );
+        assert!(!parse("127.0.0.1 1153").unwrap().auto_join);
+        assert!(parse("127.0.0.1 1153").unwrap().reconnect);
+        assert!(parse("127.0.0.1 1153 --tls").unwrap().tls);
+    }
+
+    #[test]
+    fn verify_profiles() {
+        let work = parse("work").unwrap();
+        assert_eq!(work.server_address, "chat.example.com:1153");
+        assert_eq!(work.nick.as_deref(), Some("alice"));
+        assert!(work.auto_join);
+        assert!(work.tls);
+
+        let home = parse("home --nick robert").unwrap();
+        assert_eq!(home.nick.as_deref(), Some("robert"));
+        assert!(!home.auto_join);
+        assert!(!home.tls);
+        assert!(home.tui);
+    }
+
+    #[test]
+    fn verify_send_and_listen() {
+        let send = parse("send --nick ci-bot 127.0.0.1 1153 deploy").unwrap();
+        assert_eq!(send.mode, Mode::Send(String::from("deploy")));
+        assert_eq!(send.server_address, "127.0.0.1:1153");
+        assert!(send.auto_join);
+        assert_eq!(
+            parse("send work hi").unwrap().mode,
+            Mode::Send(String::from("hi"))
+        );
+        assert!(parse("send 127.0.0.1 1153 hi").is_err()); // no nick
+        assert!(parse("send --nick bot 127.0.0.1 1153").is_err()); // no message
+
+        let listen = parse("listen 127.0.0.1 1153").unwrap();
+        assert_eq!(listen.mode, Mode::Listen);
+        let nick = listen.nick.unwrap();
+        assert!(nick.starts_with("listener-"));
+        assert_ne!(parse("listen 127.0.0.1 1153").unwrap().nick, Some(nick));
+        assert_eq!(parse("listen work").unwrap().nick.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn verify_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("127.0.0.1 1153 extra").is_err());
+        assert!(parse("127.0.0.1 port").is_err());
+        assert!(parse("127.0.0.1 1153 --auto-join").is_err());
+        assert!(parse("127.0.0.1 1153 --nick").is_err());
+        assert!(parse("127.0.0.1 1153 --verbose").is_err());
+        assert!(parse("127.0.0.1 1153 --log-format xml").is_err());
+        assert!(parse("school").unwrap_err().contains("no profile named"));
+        assert!(parse_profiles("port = 1").is_err());
+        assert!(parse_profiles("[a]\nport = many")
+            .unwrap_err()
+            .starts_with("line 2"));
+    }
+}
@@ -0,0 +1,145 @@
+// non-interactive modes, for shell scripts.
+//
+// `send` connects, joins, sends a message, waits until the server has handled
+// it and ends, telling how it went with the exit status, i.e.:
+//
+//   client send --nick ci-bot 127.0.0.1 1153 "deploy done" || echo "not sent"
+//
+// `listen` joins and prints what happens in the chat as JSON lines, one per
+// event, until the server closes the connection, i.e.:
+//
+//   {"time":"2024-05-01T10:20:30Z","type":"message","nick":"bob","text":"hi"}
+
+use crate::connect;
+use crate::options::Options;
+
+use chat_client::{format_timestamp, json_string, Connection, Event};
+
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_USAGE: i32 = 1; // wrong options
+pub const EXIT_CONNECTION: i32 = 2; // could not connect, or the connection was lost
+pub const EXIT_JOIN_REFUSED: i32 = 3; // the nick was not accepted
+pub const EXIT_MESSAGE_REFUSED: i32 = 4; // the message was not accepted, i.e.: muted
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10); // for each answer of the server
+
+pub fn send(options: &Options, text: &str) -> i32 {
+    let mut connection = match join(options, Some(SEND_TIMEOUT)) {
+        Ok(connection) => connection,
+        Err(status) => return status,
+    };
+    if let Err(error) = connection.send_confirmed(text) {
+        eprintln!(">>> the message was not sent: {}", error);
+        return match error.kind() {
+            io::ErrorKind::Other => EXIT_MESSAGE_REFUSED,
+            _ => EXIT_CONNECTION,
+        };
+    }
+    let _ = connection.sender().leave();
+    EXIT_OK
+}
+
+pub fn listen(options: &Options) -> i32 {
+    let mut connection = match join(options, None) {
+        Ok(connection) => connection,
+        Err(status) => return status,
+    };
+    let mut stdout = io::stdout().lock();
+    for event in connection.events() {
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                eprintln!(">>> connection to the server lost: {}", error);
+                return EXIT_CONNECTION;
+            }
+        };
+        let line = event_json(&event, SystemTime::now());
+        if writeln!(stdout, "{}", line)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return EXIT_OK; // nobody is reading anymore, i.e.: `| head`
+        }
+    }
+    EXIT_OK
+}
+
+// connect and join with the nick of the options.
+fn join(options: &Options, timeout: Option<Duration>) -> Result<Connection, i32> {
+    let nick = options.nick.as_deref().ok_or(EXIT_USAGE)?;
+    let mut connection = connect(&options.server_address, options.tls).map_err(|error| {
+        eprintln!(
+            ">>> could not connect to {}: {}",
+            options.server_address, error
+        );
+        EXIT_CONNECTION
+    })?;
+    let _ = connection.set_read_timeout(timeout);
+    connection.join(nick).map_err(|error| {
+        eprintln!(">>> could not join the chat: {}", error);
+        match error.kind() {
+            io::ErrorKind::Other => EXIT_JOIN_REFUSED,
+            _ => EXIT_CONNECTION,
+        }
+    })?;
+    Ok(connection)
+}
+
+// an event as a JSON object, with its type and fields.
+fn event_json(event: &Event, time: SystemTime) -> String {
+    let fields = match event {
+        Event::Message { nick, text } => format!(
+            "\"type\":\"message\",\"nick\":{},\"text\":{}",
+            json_string(nick),
+            json_string(text)
+        ),
+        Event::PrivateMessage { nick, text } => format!(
+            "\"type\":\"private\",\"nick\":{},\"text\":{}",
+            json_string(nick),
+            json_string(text)
+        ),
+        Event::Joined(nick) => format!("\"type\":\"joined\",\"nick\":{}", json_string(nick)),
+        Event::Left(nick) => format!("\"type\":\"left\",\"nick\":{}", json_string(nick)),
+        Event::Notice(text) => format!("\"type\":\"notice\",\"text\":{}", json_string(text)),
+        Event::Response { name, lines } => {
+            let lines: Vec<String> = lines.iter().map(|line| json_string(line)).collect();
+            format!(
+                "\"type\":\"response\",\"name\":{},\"lines\":[{}]",
+                json_string(name),
+                lines.join(",")
+            )
+        }
+        Event::Line(line) => format!("\"type\":\"line\",\"text\":{}", json_string(line)),
+    };
+    format!("{{\"time\":\"{}\",{}}}", format_timestamp(time), fields)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn verify_event_json() {
+        let message = Event::Message {
+            nick: String::from("bob"),
+            text: String::from("say \"hi\""),
+        };
+        assert_eq!(
+            event_json(&message, UNIX_EPOCH),
+            "{\"time\":\"1970-01-01T00:00:00Z\",\"type\":\"message\",\"nick\":\"bob\",\"text\":\"say \\\"hi\\\"\"}"
+        );
+        let who = Event::Response {
+            name: String::from("WHO"),
+            lines: vec![String::from("alice"), String::from("bob")],
+        };
+        assert!(event_json(&who, UNIX_EPOCH)
+            .ends_with("\"name\":\"WHO\",\"lines\":[\"alice\",\"bob\"]}"));
+        assert!(event_json(&Event::Left(String::from("bob")), UNIX_EPOCH)
+            .ends_with("\"type\":\"left\",\"nick\":\"bob\"}"));
+    }
+}
@@ -0,0 +1,181 @@
+// the stream to the server, plain TCP or TLS.
+//
+// The reading and the sending side of a connection are used from different
+// threads, so a TLS stream is a clone of the socket for each side, sharing
+// the TLS session. The session is only locked to decrypt what was already
+// read or to encrypt what is sent: the reading side waits for the socket
+// without it, so sending never waits for the server to say something.
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// bytes read from the socket at once, as rustls does.
+const READ_SIZE: usize = 4096;
+
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(TlsStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        Ok(match self {
+            Stream::Plain(tcp) => Stream::Plain(tcp.try_clone()?),
+            Stream::Tls(tls) => Stream::Tls(TlsStream {
+                tcp: tls.tcp.try_clone()?,
+                session: Arc::clone(&tls.session),
+            }),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    // close the connection. A TLS session tells the server first.
+    pub fn shutdown(&self) -> io::Result<()> {
+        if let Stream::Tls(tls) = self {
+            let mut session = tls.session.lock().unwrap();
+            session.send_close_notify();
+            let _ = session.write_tls(&mut &tls.tcp);
+        }
+        self.tcp().shutdown(Shutdown::Both)
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(tcp) => tcp,
+            Stream::Tls(tls) => &tls.tcp,
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.read(buf),
+            Stream::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.write(buf),
+            Stream::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(tcp) => tcp.flush(),
+            Stream::Tls(_) => Ok(()), // every write is sent right away
+        }
+    }
+}
+
+pub struct TlsStream {
+    tcp: TcpStream,
+    session: Arc<Mutex<ClientConnection>>,
+}
+
+impl TlsStream {
+    // do the handshake over a connected socket. The certificate of the server
+    // must be valid for `server_name`.
+    pub fn handshake(
+        mut tcp: TcpStream,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<TlsStream> {
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|_| io::Error::other(format!("invalid server name `{}`", server_name)))?;
+        let mut session = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
+        while session.is_handshaking() {
+            session.complete_io(&mut tcp)?;
+        }
+        Ok(TlsStream {
+            tcp,
+            session: Arc::new(Mutex::new(session)),
+        })
+    }
+
+    // send what the session has to send, i.e.: an alert, or encrypted data.
+    fn send_pending(&self, session: &mut ClientConnection) -> io::Result<()> {
+        while session.wants_write() {
+            session.write_tls(&mut &self.tcp)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut data = [0; READ_SIZE];
+        loop {
+            match self.session.lock().unwrap().reader().read(buf) {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+            // nothing decrypted yet: wait for the server
+            let read = self.tcp.read(&mut data)?;
+            let mut session = self.session.lock().unwrap();
+            session.read_tls(&mut &data[..read])?; // an empty read tells the end of the stream
+            let processed = session.process_new_packets();
+            self.send_pending(&mut session)?;
+            processed.map_err(io::Error::other)?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = self.session.lock().unwrap();
+        let written = session.writer().write(buf)?;
+        self.send_pending(&mut session)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the configuration checking the certificate of the server against the
+// usual certificate authorities.
+pub fn default_config() -> Arc<ClientConfig> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+// the host of an `address:port`, without the brackets of an IPv6 address.
+pub fn host_of(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_host_of() {
+        assert_eq!(host_of("chat.example.com:1153"), "chat.example.com");
+        assert_eq!(host_of("127.0.0.1:1153"), "127.0.0.1");
+        assert_eq!(host_of("[::1]:1153"), "::1");
+        assert_eq!(host_of("localhost"), "localhost");
+    }
+}
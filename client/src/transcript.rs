@@ -0,0 +1,273 @@
+// transcript of the chat: every line sent to the server and received from it,
+// with the time, appended to a file per server, room and day in the directory
+// given with --log, i.e.:
+//
+//   logs/127.0.0.1_1153/main/2024-05-01.log
+//   logs/127.0.0.1_1153/@bob/2024-05-01.log
+//
+// The server has a single chat, the `main` room; the private messages with
+// each user are kept apart, in a room named after the user. The days are UTC,
+// and a new file is started when the day changes.
+//
+// The lines are plain text, `<` for the received ones and `>` for the sent:
+//
+//   2024-05-01T10:20:30Z < [bob] hi
+//   2024-05-01T10:20:35Z > hello bob
+//
+// or JSON lines, with --log-format json:
+//
+//   {"time":"2024-05-01T10:20:30Z","room":"main","direction":"received","line":"[bob] hi"}
+
+use crate::editing::has_password;
+
+use chat_client::{format_timestamp, json_string, Event};
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+pub const MAIN_ROOM: &str = "main";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Format, String> {
+        match text {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown log format `{}`, expected text or json",
+                text
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Sent,
+    Received,
+}
+
+pub struct Transcript {
+    directory: PathBuf, // directory of the server
+    format: Format,
+    files: HashMap<String, (String, File)>, // file open for each room, with its day
+}
+
+impl Transcript {
+    pub fn open(directory: &Path, server_address: &str, format: Format) -> io::Result<Transcript> {
+        let directory = directory.join(file_name(server_address));
+        fs::create_dir_all(&directory)?;
+        Ok(Transcript {
+            directory,
+            format,
+            files: HashMap::new(),
+        })
+    }
+
+    // the lines of an event from the server. A transcript that cannot be
+    // written does not stop the chat, so the errors are ignored.
+    pub fn received(&mut self, event: &Event) {
+        let room = match event {
+            Event::PrivateMessage { nick, .. } => private_room(nick),
+            _ => String::from(MAIN_ROOM),
+        };
+        let now = SystemTime::now();
+        for line in event.to_string().lines() {
+            let _ = self.write(now, &room, Direction::Received, line);
+        }
+    }
+
+    // a line sent to the server. The password of OPER is not written.
+    pub fn sent(&mut self, line: &str) {
+        let line = if has_password(line) {
+            "OPER ****"
+        } else {
+            line
+        };
+        let _ = self.write(SystemTime::now(), &sent_room(line), Direction::Sent, line);
+    }
+
+    fn write(
+        &mut self,
+        time: SystemTime,
+        room: &str,
+        direction: Direction,
+        line: &str,
+    ) -> io::Result<()> {
+        let timestamp = format_timestamp(time);
+        let day = &timestamp[..10];
+
+        let file = match self.files.get_mut(room) {
+            Some((file_day, file)) if file_day == day => file,
+            _ => {
+                let extension = match self.format {
+                    Format::Text => "log",
+                    Format::Json => "jsonl",
+                };
+                let directory = self.directory.join(file_name(room));
+                fs::create_dir_all(&directory)?;
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(directory.join(format!("{}.{}", day, extension)))?;
+                self.files.insert(room.to_string(), (day.to_string(), file));
+                &mut self.files.get_mut(room).unwrap().1
+            }
+        };
+
+        let entry = match self.format {
+            Format::Text => {
+                let arrow = match direction {
+                    Direction::Sent => '>',
+                    Direction::Received => '<',
+                };
+                format!("{} {} {}\n", timestamp, arrow, line)
+            }
+            Format::Json => {
+                let direction = match direction {
+                    Direction::Sent => "sent",
+                    Direction::Received => "received",
+                };
+                format!(
+                    "{{\"time\":\"{}\",\"room\":{},\"direction\":\"{}\",\"line\":{}}}\n",
+                    timestamp,
+                    json_string(room),
+                    direction,
+                    json_string(line)
+                )
+            }
+        };
+        file.write_all(entry.as_bytes())
+    }
+}
+
+fn private_room(nick: &str) -> String {
+    format!("@{}", nick)
+}
+
+// the room of a line sent: `MSG <nick> <text>` goes to the private room of
+// the user, and anything else to the chat.
+fn sent_room(line: &str) -> String {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("MSG"), Some(nick)) => private_room(nick),
+        _ => String::from(MAIN_ROOM),
+    }
+}
+
+// a name that can be used for a file or a directory.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "@.-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("chat_transcript_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn verify_rooms() {
+        assert_eq!(sent_room("MSG bob hi"), "@bob");
+        assert_eq!(sent_room("hello MSG bob"), "main");
+        assert_eq!(file_name("127.0.0.1:1153"), "127.0.0.1_1153");
+        assert_eq!(file_name("../x"), ".._x");
+    }
+
+    #[test]
+    fn verify_text_transcript_rotates_every_day() {
+        let directory = temp_directory("text");
+        let mut transcript = Transcript::open(&directory, "localhost:1234", Format::Text).unwrap();
+        let day = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        transcript
+            .write(day, "main", Direction::Received, "[bob] hi")
+            .unwrap();
+        transcript
+            .write(day, "main", Direction::Sent, "hello")
+            .unwrap();
+        transcript
+            .write(
+                day + Duration::from_secs(86400),
+                "main",
+                Direction::Sent,
+                "bye",
+            )
+            .unwrap();
+
+        let room = directory.join("localhost_1234").join("main");
+        assert_eq!(
+            fs::read_to_string(room.join("2024-02-29.log")).unwrap(),
+            "2024-02-29T12:34:56Z < [bob] hi\n2024-02-29T12:34:56Z > hello\n"
+        );
+        assert_eq!(
+            fs::read_to_string(room.join("2024-03-01.log")).unwrap(),
+            "2024-03-01T12:34:56Z > bye\n"
+        );
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn verify_passwords_are_masked() {
+        let directory = temp_directory("oper");
+        let mut transcript = Transcript::open(&directory, "localhost:1234", Format::Text).unwrap();
+        transcript.sent("OPER s3cret");
+
+        let room = directory.join("localhost_1234").join("main");
+        let file = fs::read_dir(room).unwrap().next().unwrap().unwrap().path();
+        assert!(fs::read_to_string(file)
+            .unwrap()
+            .ends_with(" > OPER ****\n"));
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn verify_json_transcript() {
+        let directory = temp_directory("json");
+        let mut transcript = Transcript::open(&directory, "localhost:1234", Format::Json).unwrap();
+        transcript
+            .write(
+                UNIX_EPOCH,
+                "@bob",
+                Direction::Received,
+                "[bob (private)] \"psst\"",
+            )
+            .unwrap();
+
+        let file = directory
+            .join("localhost_1234")
+            .join("@bob")
+            .join("1970-01-01.jsonl");
+        assert_eq!(
+            fs::read_to_string(file).unwrap(),
+            "{\"time\":\"1970-01-01T00:00:00Z\",\"room\":\"@bob\",\"direction\":\"received\",\"line\":\"[bob (private)] \\\"psst\\\"\"}\n"
+        );
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
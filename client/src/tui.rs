@@ -0,0 +1,585 @@
+// full screen terminal interface: the messages, the users in the chat, the
+// input line and a status bar, i.e.:
+//
+//   ┌chat──────────────────────────────┐┌users───────┐
+//   │>>> welcome to the chat, alice!   ││alice       │
+//   │[bob] hi alice                    ││bob         │
+//   └──────────────────────────────────┘└────────────┘
+//   ┌message───────────────────────────────────────────┐
+//   │hello bob                                         │
+//   └──────────────────────────────────────────────────┘
+//    connected to 127.0.0.1:1234 | alice | 2 users
+//
+// The server is read by a separate thread, that sends the events to the
+// interface over a channel, and reconnects like the line mode does. The input
+// line shares its history with the line mode, and Tab completes the commands
+// and the nicknames of the users list.
+
+use crate::editing;
+use crate::{connect, next_backoff, resume_session, send_or_queue, SharedSession, INITIAL_BACKOFF};
+
+use chat_client::{Connection, Event};
+
+use ratatui::crossterm::event::{
+    self as terminal_event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use rustyline::history::FileHistory;
+
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const MAX_SCROLLBACK: usize = 1000; // lines of the chat that are kept
+const USERS_WIDTH: u16 = 24;
+
+// what the interface is told by the thread reading the server.
+enum Update {
+    Server(Event),
+    Status(Status),
+}
+
+// state of the connection, shown in the status bar.
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    Connected,
+    Reconnecting(Duration), // waiting this long before the next attempt
+    Closed,                 // after LEAVE, or a drop with --no-reconnect: the client ends
+}
+
+pub fn run(
+    server_address: &str,
+    connection: Connection,
+    session: &SharedSession,
+) -> io::Result<()> {
+    let (updates, receiver) = mpsc::channel();
+    read_server(connection, server_address, session, updates);
+
+    let mut terminal = ratatui::init();
+    let mut app = App::new(server_address);
+    let mut history = editing::load_history();
+    app.input.history = history.iter().cloned().collect();
+    let result = run_app(&mut terminal, &mut app, &receiver, session, &mut history);
+    ratatui::restore();
+    result
+}
+
+fn run_app(
+    terminal: &mut ratatui::DefaultTerminal,
+    app: &mut App,
+    updates: &Receiver<Update>,
+    session: &SharedSession,
+    history: &mut FileHistory,
+) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if terminal_event::poll(Duration::from_millis(100))? {
+            if let terminal_event::Event::Key(key) = terminal_event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if let Some(line) = app.handle_key(key) {
+                        editing::remember(history, &line);
+                        if let Some(echo) = app.own_line(&line) {
+                            app.add_message(&echo);
+                        }
+                        app.send(&line, session);
+                    }
+                }
+            }
+        }
+        while let Ok(update) = updates.try_recv() {
+            if let Some(line) = app.handle_update(update) {
+                app.send(&line, session);
+            }
+        }
+    }
+    Ok(())
+}
+
+// read the events of the server in a separate thread, reconnecting when the
+// connection drops.
+fn read_server(
+    connection: Connection,
+    server_address: &str,
+    session: &SharedSession,
+    updates: Sender<Update>,
+) {
+    let server_address = server_address.to_owned();
+    let session = Arc::clone(session);
+    thread::spawn(move || {
+        let mut connection = connection;
+        loop {
+            let _ = updates.send(Update::Status(Status::Connected));
+            for event in connection.events() {
+                match event {
+                    Ok(event) => {
+                        session.lock().unwrap().received(&event);
+                        if updates.send(Update::Server(event)).is_err() {
+                            return; // the interface ended
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            {
+                let mut session = session.lock().unwrap();
+                session.stream = None;
+                if session.leaving || !session.reconnect {
+                    let _ = updates.send(Update::Status(Status::Closed));
+                    return;
+                }
+            }
+
+            let tls = session.lock().unwrap().tls;
+            let mut delay = INITIAL_BACKOFF;
+            connection = loop {
+                let _ = updates.send(Update::Status(Status::Reconnecting(delay)));
+                thread::sleep(delay);
+                match connect(&server_address, tls) {
+                    Ok(connection) => break connection,
+                    Err(_) => delay = next_backoff(delay),
+                }
+            };
+            resume_session(connection.sender(), &session);
+        }
+    });
+}
+
+// everything shown on the screen.
+struct App {
+    server_address: String,
+    status: Status,
+    nick: Option<String>,
+    messages: Vec<String>,
+    scroll: usize, // lines scrolled back from the last one
+    users: BTreeSet<String>,
+    full_who_sent: bool, // a WHO without pattern was sent, so its answer lists everybody
+    auto_who_sent: bool, // the interface sent that WHO itself, so its answer is not shown
+    input: InputLine,
+    quit: bool,
+}
+
+impl App {
+    fn new(server_address: &str) -> App {
+        App {
+            server_address: server_address.to_string(),
+            status: Status::Connected,
+            nick: None,
+            messages: Vec::new(),
+            scroll: 0,
+            users: BTreeSet::new(),
+            full_who_sent: false,
+            auto_who_sent: false,
+            input: InputLine::default(),
+            quit: false,
+        }
+    }
+
+    // send a line typed by the user, or by the interface itself.
+    fn send(&mut self, line: &str, session: &SharedSession) {
+        if line.trim() == "WHO" {
+            self.full_who_sent = true;
+        }
+        if let Some(pending) = send_or_queue(line, session) {
+            self.add_message(&format!(
+                ">>> offline: message queued ({} pending)",
+                pending
+            ));
+        }
+    }
+
+    // how a line typed by the user is shown, as the server does not send the
+    // messages back to their sender. The commands are not shown.
+    fn own_line(&self, line: &str) -> Option<String> {
+        let nick = self.nick.as_deref()?;
+        let mut words = line.split_whitespace();
+        match words.next()? {
+            "MSG" => {
+                let to = words.next()?;
+                let text = line.trim_start()[3..].trim_start()[to.len()..].trim_start();
+                // without a text the server refuses it
+                (!text.is_empty()).then(|| format!("[{} (private to {})] {}", nick, to, text))
+            }
+            command if editing::COMMANDS.contains(&command) => None,
+            _ => Some(format!("[{}] {}", nick, line)),
+        }
+    }
+
+    fn add_message(&mut self, text: &str) {
+        self.messages.extend(text.lines().map(String::from));
+        let excess = self.messages.len().saturating_sub(MAX_SCROLLBACK);
+        self.messages.drain(..excess);
+    }
+
+    // apply an update from the server. It returns a line to send back, if
+    // the interface needs one.
+    fn handle_update(&mut self, update: Update) -> Option<String> {
+        let event = match update {
+            Update::Status(status) => {
+                if status == Status::Closed {
+                    self.quit = true;
+                }
+                if let Status::Reconnecting(_) = status {
+                    // the list is asked again after joining
+                    self.users.clear();
+                    self.nick = None;
+                }
+                self.status = status;
+                return None;
+            }
+            Update::Server(event) => event,
+        };
+        match &event {
+            Event::Response { name, .. } if name == "WHO" && self.auto_who_sent => {
+                self.auto_who_sent = false;
+            }
+            event => self.add_message(&event.to_string()),
+        }
+
+        match event {
+            Event::Joined(nick) => {
+                self.users.insert(nick);
+            }
+            Event::Left(nick) => {
+                self.users.remove(&nick);
+            }
+            Event::Response { name, lines } if name == "WHO" && self.full_who_sent => {
+                self.full_who_sent = false;
+                self.users = lines
+                    .iter()
+                    .filter_map(|line| line.split_whitespace().next())
+                    .map(String::from)
+                    .collect();
+            }
+            Event::Notice(text) => {
+                // i.e.: welcome to the chat, alice! you are the only user here
+                // The names have no spaces, but can have a '!'
+                let nick = text
+                    .strip_prefix("welcome to the chat, ")?
+                    .split_whitespace()
+                    .next()?
+                    .strip_suffix('!')?;
+                self.nick = Some(nick.to_string());
+                self.auto_who_sent = true;
+                return Some(String::from("WHO"));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // apply a key. It returns the line to send, when one is entered.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<String> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') | KeyCode::Char('d') if control => self.quit = true,
+            KeyCode::Enter => return self.input.submit(),
+            KeyCode::Tab => self.input.complete(&self.users),
+            KeyCode::PageUp => self.scroll = (self.scroll + 10).min(self.messages.len()),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            _ => self.input.edit(key),
+        }
+        None
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main_area, input_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [messages_area, users_area] =
+            Layout::horizontal([Constraint::Min(10), Constraint::Length(USERS_WIDTH)])
+                .areas(main_area);
+
+        // the last lines that fit, wrapped to the width of the pane
+        let width = messages_area.width.saturating_sub(2) as usize;
+        let height = messages_area.height.saturating_sub(2) as usize;
+        let lines: Vec<String> = self
+            .messages
+            .iter()
+            .flat_map(|line| wrap(line, width))
+            .collect();
+        let end = lines.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        let title = if self.scroll > 0 {
+            "chat (scrolled back)"
+        } else {
+            "chat"
+        };
+        frame.render_widget(
+            Paragraph::new(lines[start..end].join("\n")).block(Block::bordered().title(title)),
+            messages_area,
+        );
+
+        let users: Vec<&str> = self.users.iter().map(String::as_str).collect();
+        frame.render_widget(
+            Paragraph::new(users.join("\n")).block(Block::bordered().title("users")),
+            users_area,
+        );
+
+        // the part of the input around the cursor
+        let width = input_area.width.saturating_sub(2) as usize;
+        let offset = (self.input.cursor + 1).saturating_sub(width);
+        let visible: String = self.input.text.iter().skip(offset).take(width).collect();
+        frame.render_widget(
+            Paragraph::new(visible).block(Block::bordered().title("message")),
+            input_area,
+        );
+        frame.set_cursor_position(Position::new(
+            input_area.x + 1 + (self.input.cursor - offset) as u16,
+            input_area.y + 1,
+        ));
+
+        frame.render_widget(Paragraph::new(self.status_line()), status_area);
+    }
+
+    fn status_line(&self) -> String {
+        let connection = match &self.status {
+            Status::Connected => format!("connected to {}", self.server_address),
+            Status::Reconnecting(delay) => {
+                format!("reconnecting to {} in {:?}", self.server_address, delay)
+            }
+            Status::Closed => String::from("disconnected"),
+        };
+        let nick = self
+            .nick
+            .as_deref()
+            .unwrap_or("not joined, type JOIN <name>");
+        format!(
+            " {} | {} | {} users | PgUp/PgDn scroll, Esc quit",
+            connection,
+            nick,
+            self.users.len()
+        )
+    }
+}
+
+// split a line in pieces of at most `width` characters.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if width == 0 || chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(width)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+// the line being typed, with its history.
+#[derive(Default)]
+struct InputLine {
+    text: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    history_pos: Option<usize>, // entry of the history being shown
+}
+
+impl InputLine {
+    fn edit(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Up => self.show_history(match self.history_pos {
+                Some(pos) => pos.checked_sub(1),
+                None => self.history.len().checked_sub(1),
+            }),
+            KeyCode::Down => match self.history_pos {
+                Some(pos) if pos + 1 < self.history.len() => self.show_history(Some(pos + 1)),
+                Some(_) => {
+                    self.history_pos = None;
+                    self.set_text("");
+                }
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn show_history(&mut self, pos: Option<usize>) {
+        if let Some(pos) = pos {
+            self.history_pos = Some(pos);
+            let entry = self.history[pos].clone();
+            self.set_text(&entry);
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+
+    // complete the word before the cursor, as far as all the candidates
+    // agree. A single candidate is completed with a space after it.
+    fn complete(&mut self, nicks: &BTreeSet<String>) {
+        let before: String = self.text[..self.cursor].iter().collect();
+        let (start, candidates) = editing::completions(&before, before.len(), nicks);
+        if candidates.is_empty() {
+            return;
+        }
+        let mut completion = editing::common_prefix(&candidates);
+        let start = before[..start].chars().count();
+        if candidates.len() == 1 {
+            completion.push(' ');
+        } else if completion.chars().count() <= self.cursor - start {
+            // the candidates differ in case, i.e.: `al` for Albert and alice,
+            // so the prefix would not keep the word typed
+            return;
+        }
+        self.text.splice(start..self.cursor, completion.chars());
+        self.cursor = start + completion.chars().count();
+    }
+
+    // take the line typed, if any, and keep it in the history.
+    fn submit(&mut self) -> Option<String> {
+        let line: String = self.text.iter().collect();
+        self.set_text("");
+        self.history_pos = None;
+        if line.trim().is_empty() {
+            return None;
+        }
+        if self.history.last() != Some(&line) && !editing::has_password(&line) {
+            self.history.push(line.clone());
+        }
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_text(input: &mut InputLine, text: &str) {
+        for c in text.chars() {
+            input.edit(key(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn verify_input_editing_and_history() {
+        let mut input = InputLine::default();
+        type_text(&mut input, "helo");
+        input.edit(key(KeyCode::Left));
+        type_text(&mut input, "l");
+        assert_eq!(input.submit(), Some(String::from("hello")));
+        type_text(&mut input, "WHO");
+        assert_eq!(input.submit(), Some(String::from("WHO")));
+        assert_eq!(input.submit(), None);
+
+        input.edit(key(KeyCode::Up));
+        input.edit(key(KeyCode::Up));
+        assert_eq!(input.text.iter().collect::<String>(), "hello");
+        input.edit(key(KeyCode::Down));
+        assert_eq!(input.text.iter().collect::<String>(), "WHO");
+        input.edit(key(KeyCode::Down));
+        assert!(input.text.is_empty());
+    }
+
+    #[test]
+    fn verify_tab_completion() {
+        let users: BTreeSet<String> = [String::from("alice"), String::from("alfred")].into();
+        let mut input = InputLine::default();
+        type_text(&mut input, "ms");
+        input.complete(&users);
+        assert_eq!(input.text.iter().collect::<String>(), "MSG ");
+        type_text(&mut input, "a");
+        input.complete(&users);
+        assert_eq!(input.text.iter().collect::<String>(), "MSG al");
+        type_text(&mut input, "i");
+        input.complete(&users);
+        type_text(&mut input, "hi");
+        assert_eq!(input.text.iter().collect::<String>(), "MSG alice hi");
+
+        // nothing in common but the case-insensitive word: it is kept
+        let users: BTreeSet<String> = [String::from("alice"), String::from("Albert")].into();
+        let mut input = InputLine::default();
+        type_text(&mut input, "MSG al");
+        input.complete(&users);
+        assert_eq!(input.text.iter().collect::<String>(), "MSG al");
+        type_text(&mut input, "b");
+        input.complete(&users);
+        assert_eq!(input.text.iter().collect::<String>(), "MSG Albert ");
+    }
+
+    #[test]
+    fn verify_users_follow_the_events() {
+        let mut app = App::new("localhost:1234");
+        let welcome = Event::Notice(String::from(
+            "welcome to the chat, alice! there are 2 users here",
+        ));
+        assert_eq!(
+            app.handle_update(Update::Server(welcome)),
+            Some(String::from("WHO"))
+        );
+        assert_eq!(app.nick.as_deref(), Some("alice"));
+
+        app.full_who_sent = true;
+        let who = Event::Response {
+            name: String::from("WHO"),
+            lines: vec![String::from("alice  active"), String::from("bob    active")],
+        };
+        app.handle_update(Update::Server(who));
+        assert!(app.messages.iter().all(|line| !line.contains("WHO"))); // asked by the interface
+        app.handle_update(Update::Server(Event::Joined(String::from("carol"))));
+        app.handle_update(Update::Server(Event::Left(String::from("bob"))));
+        assert_eq!(app.users.iter().collect::<Vec<_>>(), ["alice", "carol"]);
+
+        app.handle_update(Update::Status(Status::Reconnecting(Duration::from_secs(1))));
+        assert!(app.users.is_empty());
+        assert!(app.status_line().contains("reconnecting"));
+
+        let welcome = Event::Notice(String::from(
+            "welcome to the chat, hey!you! you are the only user here",
+        ));
+        app.handle_update(Update::Server(welcome));
+        assert_eq!(app.nick.as_deref(), Some("hey!you"));
+    }
+
+    #[test]
+    fn verify_own_lines_are_shown() {
+        let mut app = App::new("localhost:1234");
+        assert_eq!(app.own_line("hi"), None); // not joined yet
+        app.nick = Some(String::from("alice"));
+        assert_eq!(app.own_line("hi all"), Some(String::from("[alice] hi all")));
+        assert_eq!(
+            app.own_line("MSG bob  see you"),
+            Some(String::from("[alice (private to bob)] see you"))
+        );
+        assert_eq!(app.own_line("WHO"), None);
+        assert_eq!(app.own_line("MSG bob"), None);
+    }
+
+    #[test]
+    fn verify_wrap() {
+        assert_eq!(wrap("abcdef", 4), ["abcd", "ef"]);
+        assert_eq!(wrap("", 4), [""]);
+    }
+}
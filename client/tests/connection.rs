@@ -0,0 +1,370 @@
+// tests of the client library against a real server, on a free port.
+
+use chat_client::rustls::pki_types::pem::PemObject;
+use chat_client::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use chat_client::rustls::{self, ClientConfig, RootCertStore, ServerConnection, StreamOwned};
+use chat_client::{Connection, Event};
+use server::logger::Level;
+use server::{EventHook, Server, ServerConfig, Verdict};
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// a certificate for localhost, signed by a certificate authority of the tests
+const TLS_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls");
+
+fn start_server() -> SocketAddr {
+    start_server_with_hook(None)
+}
+
+fn start_server_with_hook(hook: Option<Arc<dyn EventHook>>) -> SocketAddr {
+    start_server_with(ServerConfig::default(), hook)
+}
+
+fn start_server_with(config: ServerConfig, hook: Option<Arc<dyn EventHook>>) -> SocketAddr {
+    let config = ServerConfig {
+        log_level: Level::Error,
+        ..config
+    };
+    server::logger::configure(&config).unwrap();
+    let mut server = Server::bind("127.0.0.1:0", config).unwrap();
+    if let Some(hook) = hook {
+        server.add_hook(hook);
+    }
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    address
+}
+
+fn connect(address: SocketAddr) -> Connection {
+    let connection = Connection::connect(address).unwrap();
+    connection
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    connection
+}
+
+// a TLS proxy in front of the chat server, as stunnel would be.
+fn start_tls_proxy(server: SocketAddr) -> SocketAddr {
+    let certificates = CertificateDer::pem_file_iter(format!("{}/server.pem", TLS_DIRECTORY))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let key = PrivateKeyDer::from_pem_file(format!("{}/server.key", TLS_DIRECTORY)).unwrap();
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .unwrap();
+    let config = Arc::new(config);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let config = Arc::clone(&config);
+            thread::spawn(move || {
+                let mut upstream = TcpStream::connect(server).unwrap();
+                // both sides are polled from this thread
+                let poll = Some(Duration::from_millis(10));
+                client.set_read_timeout(poll).unwrap();
+                upstream.set_read_timeout(poll).unwrap();
+                let mut client = StreamOwned::new(ServerConnection::new(config).unwrap(), client);
+                while forward(&mut client, &mut upstream) && forward(&mut upstream, &mut client) {}
+            });
+        }
+    });
+    address
+}
+
+// copy what can be read now. False once a side is closed.
+fn forward(from: &mut impl Read, to: &mut impl Write) -> bool {
+    let mut buffer = [0; 4096];
+    match from.read(&mut buffer) {
+        Ok(0) => false,
+        Ok(read) => to
+            .write_all(&buffer[..read])
+            .and_then(|_| to.flush())
+            .is_ok(),
+        Err(error) => matches!(
+            error.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ),
+    }
+}
+
+// a TLS configuration trusting the certificate authority of the tests.
+fn tls_config() -> Arc<ClientConfig> {
+    let authority = CertificateDer::from_pem_file(format!("{}/ca.pem", TLS_DIRECTORY)).unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(authority).unwrap();
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+// the next event that is not a notice.
+fn next_chat_event(connection: &mut Connection) -> Event {
+    loop {
+        match connection.next_event().unwrap().unwrap() {
+            Event::Notice(_) => continue,
+            event => return event,
+        }
+    }
+}
+
+// skip the events until a notice starting with `start`.
+fn next_notice(connection: &mut Connection, start: &str) {
+    loop {
+        if let Event::Notice(text) = connection.next_event().unwrap().unwrap() {
+            if text.starts_with(start) {
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn bots_can_join_and_chat() {
+    let address = start_server();
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+    let mut bot = connect(address);
+    bot.join("bot").unwrap();
+
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Joined(String::from("bot"))
+    );
+    bot.send("beep").unwrap();
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Message {
+            nick: String::from("bot"),
+            text: String::from("beep")
+        }
+    );
+    alice.send_private("bot", "hello").unwrap();
+    assert_eq!(
+        next_chat_event(&mut bot),
+        Event::PrivateMessage {
+            nick: String::from("alice"),
+            text: String::from("hello")
+        }
+    );
+}
+
+#[test]
+fn framed_responses_are_a_single_event() {
+    let address = start_server();
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+
+    alice.sender().send_line("WHO").unwrap();
+    match next_chat_event(&mut alice) {
+        Event::Response { name, lines } => {
+            assert_eq!(name, "WHO");
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].starts_with("alice "));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn join_fails_with_the_reason_of_the_server() {
+    let address = start_server();
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+    let mut other = connect(address);
+
+    let error = other.join("alice").unwrap_err();
+    assert_eq!(error.to_string(), "the name alice is already in use");
+    other.join("alicia").unwrap();
+
+    // already in the chat, the server does not answer
+    assert!(other.join("alice2").is_err());
+}
+
+// refuses to let anybody in, without telling why.
+struct Doorman;
+
+impl EventHook for Doorman {
+    fn on_command(&self, _nick: Option<&str>, line: &str) -> Verdict {
+        if line.starts_with("JOIN") {
+            Verdict::Veto(String::new())
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+#[test]
+fn join_fails_when_vetoed_without_a_reason() {
+    let address = start_server_with_hook(Some(Arc::new(Doorman)));
+    let mut alice = connect(address);
+    let error = alice.join("alice").unwrap_err();
+    assert_eq!(error.to_string(), "the name was not accepted");
+}
+
+// refuses the messages that mention secrets.
+struct Censor;
+
+impl EventHook for Censor {
+    fn on_message(&self, _nick: &str, text: &str) -> Verdict {
+        if text.contains("secret") {
+            Verdict::Veto(String::from("no secrets here"))
+        } else if text.starts_with("psst") {
+            Verdict::Veto(String::new())
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+#[test]
+fn confirmed_messages_wait_for_the_server() {
+    let address = start_server_with_hook(Some(Arc::new(Censor)));
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+    let mut bot = connect(address);
+    bot.join("bot").unwrap();
+
+    bot.send_confirmed("deploy done").unwrap();
+    let error = bot.send_confirmed("the secret is 42").unwrap_err();
+    assert_eq!(error.to_string(), "no secrets here");
+    let error = bot.send_confirmed("psst").unwrap_err();
+    assert_eq!(error.to_string(), "the message was refused");
+
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Joined(String::from("bot"))
+    );
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Message {
+            nick: String::from("bot"),
+            text: String::from("deploy done")
+        }
+    );
+}
+
+#[test]
+fn muted_users_get_their_messages_refused() {
+    let address = start_server_with(
+        ServerConfig {
+            oper_password: Some(String::from("sesame")),
+            ..Default::default()
+        },
+        None,
+    );
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+    alice.send("OPER sesame").unwrap();
+    let mut bot = connect(address);
+    bot.join("bot").unwrap();
+
+    // the notice of being muted comes before the refusal
+    alice.send("MUTE bot").unwrap();
+    next_notice(&mut alice, "bot muted");
+    let error = bot.send_confirmed("hello").unwrap_err();
+    assert!(error.to_string().starts_with("you are muted"), "{}", error);
+
+    // being unmuted is not a refusal
+    alice.send("UNMUTE bot").unwrap();
+    next_notice(&mut alice, "bot unmuted");
+    bot.send_confirmed("hello again").unwrap();
+}
+
+#[test]
+fn messages_too_fast_are_refused() {
+    let address = start_server_with(
+        ServerConfig {
+            rate_messages_per_sec: 1.0,
+            rate_message_burst: 3.0,
+            rate_bytes_per_sec: 100.0,
+            rate_byte_burst: 100.0,
+            warnings_before_mute: 10,
+            ..Default::default()
+        },
+        None,
+    );
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+
+    // too many bytes
+    let mut bot = connect(address);
+    bot.join("bot").unwrap();
+    let error = bot.send_confirmed(&"x".repeat(150)).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("you are sending messages too fast"));
+
+    // too many lines: JOIN and HELP took two of them
+    let mut bob = connect(address);
+    bob.join("bob").unwrap();
+    bob.send_confirmed("hello").unwrap();
+    let error = bob.send_confirmed("hello again").unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("you are sending messages too fast"));
+    let mut event = next_chat_event(&mut alice);
+    while let Event::Joined(_) = event {
+        event = next_chat_event(&mut alice);
+    }
+    assert_eq!(
+        event,
+        Event::Message {
+            nick: String::from("bob"),
+            text: String::from("hello")
+        }
+    );
+}
+
+#[test]
+fn confirmed_messages_need_to_join() {
+    let address = start_server();
+    let mut bot = connect(address);
+    let error = bot.send_confirmed("hello").unwrap_err();
+    assert_eq!(error.to_string(), "join the chat first");
+}
+
+#[test]
+fn bots_can_chat_over_tls() {
+    let address = start_server();
+    let proxy = start_tls_proxy(address);
+    let mut alice = connect(address);
+    alice.join("alice").unwrap();
+
+    let mut bot = Connection::connect_tls_with(proxy, "localhost", tls_config()).unwrap();
+    bot.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    bot.join("bot").unwrap();
+    bot.send_confirmed("beep").unwrap();
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Joined(String::from("bot"))
+    );
+    assert_eq!(
+        next_chat_event(&mut alice),
+        Event::Message {
+            nick: String::from("bot"),
+            text: String::from("beep")
+        }
+    );
+    alice.send("boop").unwrap();
+    assert_eq!(
+        next_chat_event(&mut bot),
+        Event::Message {
+            nick: String::from("alice"),
+            text: String::from("boop")
+        }
+    );
+
+    // the certificate is not for that name, nor signed by a usual authority
+    assert!(Connection::connect_tls_with(proxy, "chat.example.com", tls_config()).is_err());
+    assert!(Connection::connect_tls(&proxy.to_string()).is_err());
+}
@@ -0,0 +1,373 @@
+// administration commands, typed in the server console or sent through the
+// admin control socket.
+//
+//   LIST                                list the connections
+//   KICK <nick|position> [reason]       disconnect a client
+//   ANNOUNCE <text>                     send a notice to everybody
+//   BAN <pattern> [duration] [reason]   ban an address, a network or a nick
+//   UNBAN <pattern>                     remove a ban
+//   BANS                                list the current bans
+//   RELOAD                              read the configuration file again
+//   STATS                               show the server counters
+//
+// The admin socket is a plain TCP socket, only listening on a loopback
+// address (see `admin_address` in the configuration), that can be used with
+// telnet or netcat. Every command gets its output followed by an empty line.
+//
+// patterns are `192.168.1.20`, `10.0.0.0/8` or `nick:troll*`, and durations
+// are given as `90s`, `15m`, `2h` or `7d`. Bans without duration are
+// permanent. Clients matching a new ban are disconnected right away.
+
+use crate::aux::*;
+use crate::bans::{Ban, BanPattern};
+use crate::commands::*;
+use crate::logger;
+use crate::state::ServerStats;
+use crate::ClientsNameArray;
+use crate::ClientsStreamArray;
+use crate::SharedState;
+use crate::MAX_CLIENTS;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+pub const ADMIN_HELP: &str = "admin commands:
+  LIST
+  KICK <nick|position> [reason]
+  ANNOUNCE <text>
+  BAN <pattern> [duration] [reason]
+  UNBAN <pattern>
+  BANS
+  RELOAD
+  STATS";
+
+// run an admin command, and return the text to show to the administrator.
+pub fn handle_admin_command(
+    line: &str,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let mut words = line.split_ascii_whitespace();
+    match words.next() {
+        Some("LIST") => admin_list(stream_array, clients_array, state),
+        Some("KICK") => {
+            let rest: Vec<&str> = words.collect();
+            admin_kick(&rest, clients_array, stream_array, state)
+        }
+        Some("ANNOUNCE") => handle_announce(line, MAX_CLIENTS, clients_array, stream_array, state),
+        Some("RELOAD") => match state.reload_config() {
+            Ok(()) => String::from("configuration reloaded"),
+            Err(error) => format!("error: {}", error),
+        },
+        Some("STATS") => admin_stats(stream_array, clients_array, state),
+        Some("BAN") => {
+            let rest: Vec<&str> = words.collect();
+            admin_ban(&rest, clients_array, stream_array, state)
+        }
+        Some("UNBAN") => match words.next().and_then(BanPattern::parse) {
+            Some(pattern) => match state.bans.lock().unwrap().remove(&pattern) {
+                Ok(true) => format!("ban on {} removed", pattern),
+                Ok(false) => format!("there is no ban on {}", pattern),
+                Err(error) => format!("error: {}", error),
+            },
+            None => String::from("usage: UNBAN <pattern>"),
+        },
+        Some("BANS") => {
+            let mut bans = state.bans.lock().unwrap();
+            bans.purge_expired(SystemTime::now());
+            if bans.bans().is_empty() {
+                String::from("no bans")
+            } else {
+                bans.bans()
+                    .iter()
+                    .map(|ban| ban.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        None => String::new(),
+        _ => String::from(ADMIN_HELP),
+    }
+}
+
+// LIST: one line per connection, with its position, address, name, and how
+// long it has been connected and idle.
+fn admin_list(
+    stream_array: &ClientsStreamArray,
+    clients_array: &ClientsNameArray,
+    state: &SharedState,
+) -> String {
+    let connected: Vec<bool> = stream_array
+        .lock()
+        .unwrap()
+        .iter()
+        .map(Option::is_some)
+        .collect();
+    let names = clients_array.lock().unwrap();
+    let infos = state.clients_info.lock().unwrap();
+    let now = Instant::now();
+
+    let mut list = format!(
+        "{:<4} {:<24} {:<20} {:<10} {:<10} flags",
+        "pos", "address", "name", "connected", "idle"
+    );
+    for (i, info) in infos.iter().enumerate() {
+        let info = match info {
+            Some(info) if connected[i] => info,
+            _ => continue,
+        };
+        let name = names[i].as_deref().unwrap_or("-");
+        let mut flags: Vec<&str> = Vec::new();
+        if info.operator {
+            flags.push("operator");
+        }
+        if info.muted_for(now).is_some() {
+            flags.push("muted");
+        }
+        list.push_str(&format!(
+            "\n{:<4} {:<24} {:<20} {:<10} {:<10} {}",
+            i,
+            info.address,
+            name,
+            format_duration(now - info.connected_at),
+            format_duration(now - info.last_activity),
+            flags.join(",")
+        ));
+    }
+    list
+}
+
+// KICK <nick|position> [reason]
+fn admin_kick(
+    args: &[&str],
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    // connections that did not JOIN yet can only be kicked by position
+    if let Some(index) = args.first().and_then(|arg| arg.parse::<usize>().ok()) {
+        // the id is taken once, so a connection that takes the slot after
+        // this is not kicked
+        let id = if index < MAX_CLIENTS {
+            state.with_client(index, |info| info.id)
+        } else {
+            None
+        };
+        let reason = ">>> you have been kicked by the administrator";
+        if !id.is_some_and(|id| close_connection(index, id, reason, stream_array, state)) {
+            return format!("there is no connection at position {}", index);
+        }
+        return format!("connection at position {} kicked", index);
+    }
+    handle_kick(
+        args,
+        "the administrator",
+        clients_array,
+        stream_array,
+        state,
+    )
+}
+
+// STATS
+fn admin_stats(
+    stream_array: &ClientsStreamArray,
+    clients_array: &ClientsNameArray,
+    state: &SharedState,
+) -> String {
+    let connections = stream_array.lock().unwrap().iter().flatten().count();
+    let users = clients_array.lock().unwrap().iter().flatten().count();
+    let stats = &state.stats;
+    format!(
+        "uptime: {}\nconnections: {}\nusers: {}\nconnections accepted: {}\nconnections rejected: {}\nmessages received: {}\nbans: {}",
+        format_duration(state.started_at.elapsed()),
+        connections,
+        users,
+        ServerStats::get(&stats.connections_accepted),
+        ServerStats::get(&stats.connections_rejected),
+        ServerStats::get(&stats.messages_received),
+        state.bans.lock().unwrap().bans().len()
+    )
+}
+
+// BAN <pattern> [duration] [reason]
+pub fn admin_ban(
+    args: &[&str],
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let pattern = match args.first().and_then(|pattern| BanPattern::parse(pattern)) {
+        Some(pattern) => pattern,
+        None => return String::from("usage: BAN <pattern> [duration] [reason]"),
+    };
+
+    let now = SystemTime::now();
+    let (expires, reason) = match args.get(1).and_then(|duration| parse_duration(duration)) {
+        Some(duration) => (Some(now + duration), args[2..].join(" ")),
+        None => (None, args[1..].join(" ")),
+    };
+
+    let ban = Ban {
+        pattern,
+        expires,
+        reason,
+    };
+    let description = ban.to_string();
+    if let Err(error) = state.bans.lock().unwrap().add(ban.clone()) {
+        return format!("error: {}", error);
+    }
+
+    logger::info("ban", &[("ban", &ban)]);
+    let banned = kick_banned_clients(&ban, clients_array, stream_array, state);
+    format!("banned {} ({} client(s) disconnected)", description, banned)
+}
+
+// disconnect the connected clients matching a ban, and return how many.
+fn kick_banned_clients(
+    ban: &Ban,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> usize {
+    let mut matching: Vec<(usize, u64)> = Vec::new();
+    {
+        // the address the clients connected from, not the one of their
+        // streams: they differ for the WebSocket users
+        let connections: Vec<Option<(SocketAddr, u64)>> = state
+            .clients_info
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|info| info.as_ref().map(|info| (info.address, info.id)))
+            .collect();
+        let names = clients_array.lock().unwrap();
+        let streams = stream_array.lock().unwrap();
+        for (i, stream) in streams.iter().enumerate() {
+            let (address, id) = match connections[i] {
+                Some(connection) if stream.is_some() => connection,
+                _ => continue,
+            };
+            let address_match = ban.pattern.matches_address(address.ip());
+            let nick_match = names[i]
+                .as_ref()
+                .is_some_and(|name| ban.pattern.matches_nick(name));
+            if address_match || nick_match {
+                matching.push((i, id));
+            }
+        }
+    }
+
+    // the client threads remove them from the chat, when they see their
+    // connections closed
+    let reason = format!(">>> you have been banned from this server ({})", ban);
+    matching
+        .iter()
+        .filter(|&&(i, id)| close_connection(i, id, &reason, stream_array, state))
+        .count()
+}
+
+// listen for admin connections in a separate thread. Every admin connection
+// gets its own thread too.
+pub fn spawn_admin_socket(
+    address: SocketAddr,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    logger::info("admin_listen", &[("address", &address)]);
+
+    let clients_array = Arc::clone(clients_array);
+    let stream_array = Arc::clone(stream_array);
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let clients_array = Arc::clone(&clients_array);
+            let stream_array = Arc::clone(&stream_array);
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                handle_admin_client(stream, &clients_array, &stream_array, &state);
+            });
+        }
+    });
+    Ok(())
+}
+
+// read admin commands from the server console, in a separate thread.
+pub fn spawn_admin_console(
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let clients_array = Arc::clone(clients_array);
+    let stream_array = Arc::clone(stream_array);
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        admin_console(
+            io::stdin().lock(),
+            io::stdout(),
+            &clients_array,
+            &stream_array,
+            &state,
+        )
+    });
+}
+
+// answer the admin commands read from `input` on the `console`. The log may
+// go to stderr or a file, but never to the console.
+fn admin_console(
+    input: impl BufRead,
+    mut console: impl Write,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        logger::info("admin_command", &[("command", &line.trim())]);
+        let output = handle_admin_command(&line, clients_array, stream_array, state);
+        if output.is_empty() {
+            continue;
+        }
+        if writeln!(console, "{}", output)
+            .and_then(|_| console.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn handle_admin_client(
+    mut stream: TcpStream,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(_) => return,
+    };
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        logger::info("admin_command", &[("command", &line.trim())]);
+        let output = handle_admin_command(&line, clients_array, stream_array, state);
+        if stream
+            .write_all(format!("{}\n\n", output).as_bytes())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
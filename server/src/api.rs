@@ -0,0 +1,213 @@
+// HTTP API, for the programs that cannot speak the chat protocol.
+//
+// When `api_address` is configured, the server answers on that address:
+//
+//   POST /rooms/main/messages   send the body, plain text, to the chat
+//   GET  /users                 names of the users, as a JSON array
+//
+// Every request needs the `api_token` of the configuration, i.e.:
+//
+//   curl -H "Authorization: Bearer s3cret" -d "deploy done" \
+//        http://127.0.0.1:1155/rooms/main/messages
+//
+// The server has a single chat, the room `main`: other rooms are not found.
+// The messages are sent to the users as a notice, `*** deploy done`.
+//
+// reference: https://datatracker.ietf.org/doc/html/rfc9110
+
+use crate::aux::{constant_time_eq, json_string};
+use crate::commands::broadcast_msg_to_other_names;
+use crate::logger;
+use crate::state::ServerStats;
+use crate::ClientsNameArray;
+use crate::ClientsStreamArray;
+use crate::SharedState;
+use crate::MAX_CLIENTS;
+use crate::MAX_MESSAGE_SIZE;
+use crate::ROOM;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// serve the API over HTTP in a separate thread.
+pub fn spawn_api_endpoint(
+    address: SocketAddr,
+    token: String,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    logger::info("api_listen", &[("address", &address)]);
+
+    let clients_array = Arc::clone(clients_array);
+    let stream_array = Arc::clone(stream_array);
+    let state = Arc::clone(state);
+    let token = Arc::new(token);
+    thread::spawn(move || {
+        // each request in its own thread, so a slow client does not hold the
+        // others back
+        for stream in listener.incoming().flatten() {
+            let token = Arc::clone(&token);
+            let clients_array = Arc::clone(&clients_array);
+            let stream_array = Arc::clone(&stream_array);
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                let served = serve_request(stream, &token, &clients_array, &stream_array, &state);
+                if let Err(error) = served {
+                    logger::debug("api_request_failed", &[("error", &error)]);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// the parts of a request the API uses.
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_ascii_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut token = None;
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "authorization" => token = value.strip_prefix("Bearer ").map(String::from),
+                "content-length" => length = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+    }
+
+    // a longer body is not read: it is rejected anyway
+    let mut body = vec![0; length.min(MAX_MESSAGE_SIZE + 1)];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        token,
+        body,
+    })
+}
+
+fn serve_request(
+    stream: TcpStream,
+    token: &str,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let request = read_request(&stream)?;
+
+    let messages_path = format!("/rooms/{}/messages", ROOM);
+    let authorized = request
+        .token
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+    let (status, body) = if !authorized {
+        (
+            "401 Unauthorized",
+            String::from("invalid or missing token\n"),
+        )
+    } else {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path == messages_path => {
+                post_message(&request.body, clients_array, stream_array, state)
+            }
+            ("GET", "/users") => ("200 OK", list_users(clients_array)),
+            (_, path) if path == messages_path || path == "/users" => (
+                "405 Method Not Allowed",
+                String::from("method not allowed\n"),
+            ),
+            _ => ("404 Not Found", String::from("not found\n")),
+        }
+    };
+
+    // only the user list is JSON
+    let content_type = match (status, request.path.as_str()) {
+        ("200 OK", "/users") => "application/json",
+        _ => "text/plain",
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let mut stream = stream;
+    stream.write_all(response.as_bytes())
+}
+
+// send a message from the API to everybody in the chat.
+fn post_message(
+    body: &[u8],
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> (&'static str, String) {
+    if body.len() > MAX_MESSAGE_SIZE {
+        return ("413 Content Too Large", String::from("message too long\n"));
+    }
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => text.trim(),
+        Err(_) => {
+            return (
+                "400 Bad Request",
+                String::from("the message is not UTF-8\n"),
+            )
+        }
+    };
+    if text.is_empty() || text.contains(['\n', '\r']) {
+        return (
+            "400 Bad Request",
+            String::from("the message must be a single line\n"),
+        );
+    }
+
+    logger::info("api_message", &[("text", &text)]);
+    ServerStats::increment(&state.stats.messages_broadcast);
+    let msg = format!("*** {}", text);
+    broadcast_msg_to_other_names(
+        msg.as_bytes(),
+        MAX_CLIENTS,
+        clients_array,
+        stream_array,
+        state,
+    );
+    ("200 OK", String::from("sent\n"))
+}
+
+// names of the users in the chat, i.e.: ["alice","bob"]
+fn list_users(clients_array: &ClientsNameArray) -> String {
+    let names: Vec<String> = clients_array
+        .lock()
+        .unwrap()
+        .iter()
+        .flatten()
+        .map(|name| json_string(name))
+        .collect();
+    format!("[{}]\n", names.join(","))
+}
@@ -19,7 +19,6 @@ pub fn first_word_u8(s: &[u8]) -> &[u8] {
     for (i, &item) in s2.iter().enumerate() {
         if item == b' ' || item == b'\t' || item == b'\n' || item == b'\r' {
             let i2 = i1 + i;
-            std::str::from_utf8(&s[i1..i2]).unwrap();
             return &s[i1..i2];
         }
     }
@@ -34,9 +33,140 @@ pub fn first_2_words(s: &str) -> (Option<&str>, Option<&str>) {
     (word1, word2)
 }
 
+// match a text against a pattern where '*' matches any sequence of characters
+// and '?' matches a single one. The comparison is case insensitive.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None; // positions to backtrack to
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = last_star {
+            // let the last '*' swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            last_star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// parse a duration like "90", "90s", "15m", "2h" or "7d". A number without
+// unit is taken as seconds.
+pub fn parse_duration(s: &str) -> Option<std::time::Duration> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last()? {
+        's' => (&s[..s.len() - 1], 1),
+        'm' => (&s[..s.len() - 1], 60),
+        'h' => (&s[..s.len() - 1], 60 * 60),
+        'd' => (&s[..s.len() - 1], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    let number: u64 = number.parse().ok()?;
+    Some(std::time::Duration::from_secs(number.checked_mul(multiplier)?))
+}
+
+// format a duration for humans, i.e.: "45s", "3m05s", "2h07m".
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+// compare two secrets, i.e.: passwords, taking the same time wherever they
+// differ, so the time does not tell how much of a guess is right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// quote a text as a JSON string.
+// reference: https://www.rfc-editor.org/rfc/rfc8259#section-7
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
 
 #[cfg(test)]  // this is to only compile it in the 'test' configuration
 mod tests {  // test ususally are defined in a local module at the end of the file
 
     use super::*; //to include everything above in the file to test
+
+    #[test]
+    fn verify_first_word_u8_without_separator() {
+        assert_eq!(first_word_u8(b"WHO"), b"WHO");
+        assert_eq!(first_word_u8(b"WHO\n"), b"WHO");
+    }
+
+    #[test]
+    fn verify_glob_match() {
+        assert!(glob_match("troll*", "TrollFace"));
+        assert!(glob_match("*b?b*", "xxbobxx"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*c", "abcd"));
+        assert!(!glob_match("bob", "bobby"));
+    }
+
+    #[test]
+    fn verify_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(std::time::Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Some(std::time::Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Some(std::time::Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(std::time::Duration::from_secs(86400)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn verify_format_duration() {
+        assert_eq!(format_duration(std::time::Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(std::time::Duration::from_secs(185)), "3m05s");
+        assert_eq!(format_duration(std::time::Duration::from_secs(7620)), "2h07m");
+    }
+
+    #[test]
+    fn verify_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn verify_json_string() {
+        assert_eq!(json_string("hi"), "\"hi\"");
+        assert_eq!(json_string("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(json_string("a\\b\u{1}"), "\"a\\\\b\\u0001\"");
+        assert_eq!(json_string("ñandú"), "\"ñandú\"");
+    }
 }
@@ -0,0 +1,322 @@
+// ban list of the server.
+//
+// A ban can target a single address (`192.168.1.20`), a network in CIDR
+// notation (`10.0.0.0/8`, `2001:db8::/32`) or a nickname pattern
+// (`nick:troll*`), and can optionally expire. Addresses are checked when a
+// connection is accepted, and nicknames when a client JOINs.
+//
+// When the server is configured with a `ban_file`, the list is loaded from
+// it at startup and saved back after every change. The file has one ban per
+// line: `<pattern> <expiry as unix seconds, or -> [reason]`.
+
+use crate::names::name_matches;
+
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BanPattern {
+    Address(IpAddr),
+    Network(IpAddr, u8),
+    Nick(String),
+}
+
+impl BanPattern {
+    pub fn parse(s: &str) -> Option<BanPattern> {
+        if let Some(nick) = s.strip_prefix("nick:") {
+            if nick.is_empty() {
+                return None;
+            }
+            return Some(BanPattern::Nick(nick.to_string()));
+        }
+
+        match s.split_once('/') {
+            Some((address, prefix)) => {
+                let address: IpAddr = address.parse().ok()?;
+                let prefix: u8 = prefix.parse().ok()?;
+                let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+                if prefix > max_prefix {
+                    return None;
+                }
+                Some(BanPattern::Network(address, prefix))
+            }
+            None => Some(BanPattern::Address(s.parse().ok()?)),
+        }
+    }
+
+    pub fn matches_address(&self, address: IpAddr) -> bool {
+        match *self {
+            BanPattern::Address(banned) => banned == address,
+            BanPattern::Network(network, prefix) => in_network(address, network, prefix),
+            BanPattern::Nick(_) => false,
+        }
+    }
+
+    pub fn matches_nick(&self, nick: &str) -> bool {
+        match self {
+            BanPattern::Nick(pattern) => name_matches(pattern, nick),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for BanPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BanPattern::Address(address) => write!(f, "{}", address),
+            BanPattern::Network(network, prefix) => write!(f, "{}/{}", network, prefix),
+            BanPattern::Nick(pattern) => write!(f, "nick:{}", pattern),
+        }
+    }
+}
+
+// check if an address belongs to the network with the given prefix length.
+fn in_network(address: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(address) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(address) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ban {
+    pub pattern: BanPattern,
+    pub expires: Option<SystemTime>, // None for a permanent ban
+    pub reason: String,
+}
+
+impl Ban {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        match self
+            .expires
+            .map(|time| time.duration_since(SystemTime::now()))
+        {
+            Some(Ok(remaining)) => write!(f, " (expires in {}s)", remaining.as_secs())?,
+            Some(Err(_)) => write!(f, " (expired)")?,
+            None => write!(f, " (permanent)")?,
+        }
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: Vec<Ban>,
+    path: Option<String>, // file where the list is persisted
+}
+
+impl BanList {
+    // load the ban list from a file. A missing file is an empty list.
+    pub fn load(path: &str) -> Result<BanList, String> {
+        let mut list = BanList {
+            bans: Vec::new(),
+            path: Some(path.to_string()),
+        };
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+            Err(error) => return Err(format!("cannot read {}: {}", path, error)),
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(3, ' ');
+            let pattern = fields.next().and_then(BanPattern::parse);
+            let expires = match fields.next() {
+                Some("-") | None => Some(None),
+                Some(secs) => secs
+                    .parse()
+                    .ok()
+                    .map(|secs| Some(UNIX_EPOCH + Duration::from_secs(secs))),
+            };
+            match (pattern, expires) {
+                (Some(pattern), Some(expires)) => list.bans.push(Ban {
+                    pattern,
+                    expires,
+                    reason: fields.next().unwrap_or("").trim().to_string(),
+                }),
+                _ => return Err(format!("{}: invalid ban at line {}", path, number + 1)),
+            }
+        }
+
+        Ok(list)
+    }
+
+    // write the list to its file, if it has one.
+    pub fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut text = String::new();
+        for ban in &self.bans {
+            let expires = match ban.expires {
+                Some(time) => time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+                    .to_string(),
+                None => String::from("-"),
+            };
+            text.push_str(&format!("{} {} {}\n", ban.pattern, expires, ban.reason));
+        }
+        fs::write(path, text).map_err(|error| format!("cannot write {}: {}", path, error))
+    }
+
+    // add a ban, replacing any previous ban with the same pattern.
+    pub fn add(&mut self, ban: Ban) -> Result<(), String> {
+        self.bans.retain(|other| other.pattern != ban.pattern);
+        self.bans.push(ban);
+        self.save()
+    }
+
+    // remove the ban with the given pattern. Returns false if there was none.
+    pub fn remove(&mut self, pattern: &BanPattern) -> Result<bool, String> {
+        let before = self.bans.len();
+        self.bans.retain(|ban| ban.pattern != *pattern);
+        if self.bans.len() == before {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    // drop the bans that already expired.
+    pub fn purge_expired(&mut self, now: SystemTime) {
+        let before = self.bans.len();
+        self.bans.retain(|ban| !ban.is_expired(now));
+        if self.bans.len() != before {
+            let _ = self.save();
+        }
+    }
+
+    pub fn bans(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    // find an active ban for the given address.
+    pub fn find_address(&self, address: IpAddr, now: SystemTime) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.pattern.matches_address(address))
+    }
+
+    // find an active ban for the given nickname.
+    pub fn find_nick(&self, nick: &str, now: SystemTime) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.pattern.matches_nick(nick))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn ban(pattern: &str, expires: Option<SystemTime>) -> Ban {
+        Ban {
+            pattern: BanPattern::parse(pattern).unwrap(),
+            expires,
+            reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn verify_pattern_parsing() {
+        assert_eq!(
+            BanPattern::parse("10.0.0.1"),
+            Some(BanPattern::Address("10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(
+            BanPattern::parse("10.0.0.0/8"),
+            Some(BanPattern::Network("10.0.0.0".parse().unwrap(), 8))
+        );
+        assert_eq!(
+            BanPattern::parse("nick:troll*"),
+            Some(BanPattern::Nick(String::from("troll*")))
+        );
+        assert_eq!(BanPattern::parse("10.0.0.0/33"), None);
+        assert_eq!(BanPattern::parse("nick:"), None);
+        assert_eq!(BanPattern::parse("somewhere"), None);
+    }
+
+    #[test]
+    fn verify_network_match() {
+        let pattern = BanPattern::parse("192.168.0.0/16").unwrap();
+        assert!(pattern.matches_address("192.168.20.1".parse().unwrap()));
+        assert!(!pattern.matches_address("192.169.0.1".parse().unwrap()));
+        let pattern = BanPattern::parse("2001:db8::/32").unwrap();
+        assert!(pattern.matches_address("2001:db8::1".parse().unwrap()));
+        assert!(!pattern.matches_address("10.0.0.1".parse().unwrap()));
+        let everything = BanPattern::parse("0.0.0.0/0").unwrap();
+        assert!(everything.matches_address("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn verify_expired_bans_do_not_match() {
+        let now = SystemTime::now();
+        let mut list = BanList::default();
+        list.add(ban("nick:bob", Some(now - Duration::from_secs(1))))
+            .unwrap();
+        list.add(ban("nick:alice", Some(now + Duration::from_secs(60))))
+            .unwrap();
+        assert!(list.find_nick("bob", now).is_none());
+        assert!(list.find_nick("Alice", now).is_some());
+        assert!(list.find_nick("ａｌｉｃｅ", now).is_some()); // fullwidth letters
+        list.purge_expired(now);
+        assert_eq!(list.bans().len(), 1);
+    }
+
+    #[test]
+    fn verify_ban_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("bans-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut list = BanList::load(path).unwrap();
+        let mut troll = ban("nick:troll*", None);
+        troll.reason = String::from("spamming the chat");
+        list.add(troll).unwrap();
+        list.add(ban(
+            "10.0.0.0/8",
+            Some(UNIX_EPOCH + Duration::from_secs(4_000_000_000)),
+        ))
+        .unwrap();
+
+        let loaded = BanList::load(path).unwrap();
+        assert_eq!(loaded.bans().len(), 2);
+        assert_eq!(loaded.bans()[0].reason, "spamming the chat");
+        assert_eq!(loaded.bans()[1].expires, list.bans()[1].expires);
+
+        let mut loaded = loaded;
+        assert!(loaded
+            .remove(&BanPattern::parse("10.0.0.0/8").unwrap())
+            .unwrap());
+        assert_eq!(BanList::load(path).unwrap().bans().len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,17 +1,26 @@
+use crate::admin::admin_ban;
 use crate::aux::*;
+use crate::bans::BanPattern;
+use crate::handlers::{CommandContext, CommandHandler};
+use crate::logger::{self, Level};
+use crate::names::{name_matches, same_name, validate_name};
+use crate::ratelimit::{FloodGuard, FloodVerdict};
+use crate::state::{ClientInfo, Mute, Presence, ServerStats};
 use crate::ClientsNameArray;
 use crate::ClientsStreamArray;
+use crate::SharedState;
 use crate::MAX_CLIENTS;
 use crate::MAX_MESSAGE_SIZE;
-use crate::MAX_NAME_LEN;
+use crate::ROOM;
 use crate::VERSION;
 
 use std::error::Error as OtherError;
-use std::fmt;
-use std::io::{Read, Write};
-use std::net::{Shutdown, TcpStream};
+use std::fmt::{self, Display};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream};
 use std::str;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 // handle the different implemented commands
 pub fn handle_commands(
@@ -19,23 +28,125 @@ pub fn handle_commands(
     index: usize,
     clients_array: &ClientsNameArray,
     stream_array: &ClientsStreamArray,
+    state: &SharedState,
 ) -> Result<(), ClientLeavedError> {
-    let str_input = str::from_utf8(input).unwrap(); // TODO: unwrap()-> ?
+    // invalid UTF-8 sequences are replaced, instead of rejecting the message
+    let mut line = String::from_utf8_lossy(input).into_owned();
+    let mut custom_command = state.commands.read().unwrap().find(&line);
+
+    if is_command(line.as_bytes()) || custom_command.is_some() {
+        let command = first_word(&line);
+        state.stats.count_command(command);
+        log_client_event(
+            Level::Debug,
+            "command",
+            index,
+            clients_array,
+            state,
+            &[("command", &command)],
+        );
+
+        let nick = get_client_name_at_position_i(index, clients_array);
+        let original = line.trim_end();
+        match state.filter_through_hooks(original, |hook, line| {
+            hook.on_command(nick.as_deref(), line)
+        }) {
+            Ok(replaced) if replaced != original => {
+                line = format!("{}\n", replaced);
+                custom_command = state.commands.read().unwrap().find(&line);
+            }
+            Ok(_) => {}
+            Err(reason) => {
+                log_client_event(
+                    Level::Info,
+                    "command_vetoed",
+                    index,
+                    clients_array,
+                    state,
+                    &[("reason", &reason)],
+                );
+                if check_say(&line) {
+                    let reply = say_refused(&reason);
+                    send_msg_to_ith_client(
+                        reply.as_bytes(),
+                        index,
+                        clients_array,
+                        stream_array,
+                        state,
+                    );
+                } else {
+                    send_veto(&reason, index, clients_array, stream_array, state);
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    let input = line.as_bytes();
+    let str_input = line.as_str();
 
     if check_join_u8(input) {
-        handle_join(input, index, clients_array, stream_array);
+        handle_join(input, index, clients_array, stream_array, state);
     } else if check_version(str_input) {
-        handle_version(index, clients_array, stream_array);
+        handle_version(index, clients_array, stream_array, state);
+    } else if check_motd(str_input) {
+        handle_motd(index, clients_array, stream_array, state);
     } else if check_who(str_input) {
-        handle_who(index, clients_array, stream_array);
+        handle_who(str_input, index, clients_array, stream_array, state);
+    } else if check_whois(str_input) {
+        handle_whois(str_input, index, clients_array, stream_array, state);
+    } else if check_away(str_input) {
+        handle_away(str_input, index, clients_array, stream_array, state);
+    } else if check_back(str_input) {
+        handle_back(index, clients_array, stream_array, state);
+    } else if check_msg(str_input) {
+        handle_msg(str_input, index, clients_array, stream_array, state);
+    } else if check_say(str_input) {
+        handle_say(str_input, index, clients_array, stream_array, state);
     } else if check_leave(str_input) {
-        handle_leave(index, clients_array, stream_array)?;
-    } else {
-        broadcast(input, index, clients_array, stream_array);
+        handle_leave(index, clients_array, stream_array, state)?;
+    } else if check_help(str_input) {
+        handle_help(index, clients_array, stream_array, state);
+    } else if check_oper(str_input) {
+        handle_oper(str_input, index, clients_array, stream_array, state);
+    } else if is_operator_command(str_input) {
+        if require_operator(index, clients_array, stream_array, state) {
+            handle_operator_command(str_input, index, clients_array, stream_array, state);
+        }
+    } else if let Some(handler) = custom_command {
+        handle_custom_command(
+            handler.as_ref(),
+            str_input,
+            index,
+            clients_array,
+            stream_array,
+            state,
+        );
+    } else if let Err(reason) = broadcast(input, index, clients_array, stream_array, state) {
+        send_veto(&reason, index, clients_array, stream_array, state);
     }
     Ok(())
 }
 
+// check if the input is one of the implemented commands, instead of a chat
+// message.
+pub fn is_command(input: &[u8]) -> bool {
+    let str_input = &String::from_utf8_lossy(input);
+    check_join_u8(input)
+        || check_version(str_input)
+        || check_motd(str_input)
+        || check_who(str_input)
+        || check_whois(str_input)
+        || check_away(str_input)
+        || check_back(str_input)
+        || check_msg(str_input)
+        || check_say(str_input)
+        || check_leave(str_input)
+        || check_help(str_input)
+        || check_oper(str_input)
+        || is_operator_command(str_input)
+}
+
 pub fn check_command(command: &str, input: &str) -> bool {
     // actually it could be used the function .starts_with()
     // ref: https://doc.rust-lang.org/std/primitive.slice.html#method.starts_with
@@ -44,8 +155,7 @@ pub fn check_command(command: &str, input: &str) -> bool {
 }
 
 pub fn check_command_u8(command: &str, input: &[u8]) -> bool {
-    let first_word_u8 = first_word_u8(input);
-    command == std::str::from_utf8(first_word_u8).unwrap()
+    command.as_bytes() == first_word_u8(input)
 }
 
 //fn check_join(input: &str) -> bool {
@@ -61,6 +171,31 @@ pub fn check_who(input: &str) -> bool {
     check_command("WHO", input)
 }
 
+// check if the WHOIS command was issued
+pub fn check_whois(input: &str) -> bool {
+    check_command("WHOIS", input)
+}
+
+// check if the AWAY command was issued
+pub fn check_away(input: &str) -> bool {
+    check_command("AWAY", input)
+}
+
+// check if the BACK command was issued
+pub fn check_back(input: &str) -> bool {
+    check_command("BACK", input)
+}
+
+// check if the MSG command was issued
+pub fn check_msg(input: &str) -> bool {
+    check_command("MSG", input)
+}
+
+// check if the SAY command was issued
+pub fn check_say(input: &str) -> bool {
+    check_command("SAY", input)
+}
+
 // check if the LEAVE command was issued.
 pub fn check_leave(input: &str) -> bool {
     check_command("LEAVE", input)
@@ -71,24 +206,68 @@ pub fn check_version(input: &str) -> bool {
     check_command("VERSION", input)
 }
 
+// check if the MOTD command was issued
+pub fn check_motd(input: &str) -> bool {
+    check_command("MOTD", input)
+}
+
+// check if the input is a built-in command, or a custom one (see
+// `CommandHandler`).
+pub fn is_any_command(input: &[u8], state: &SharedState) -> bool {
+    is_command(input)
+        || state
+            .commands
+            .read()
+            .unwrap()
+            .find(&String::from_utf8_lossy(input))
+            .is_some()
+}
+
+// check if the HELP command was issued
+pub fn check_help(input: &str) -> bool {
+    check_command("HELP", input)
+}
+
+// check if the OPER command was issued
+pub fn check_oper(input: &str) -> bool {
+    check_command("OPER", input)
+}
+
+// check if one of the commands reserved to operators was issued
+pub fn is_operator_command(input: &str) -> bool {
+    ["KICK", "MUTE", "UNMUTE", "BAN", "ANNOUNCE"]
+        .iter()
+        .any(|command| check_command(command, input))
+}
+
 // LEAVE command: removes the user from the chat, and close the connection.
 pub fn handle_leave(
     index: usize,
     clients_array: &ClientsNameArray,
     stream_array: &ClientsStreamArray,
+    state: &SharedState,
 ) -> Result<(), ClientLeavedError> {
     let name_i = get_client_name_at_position_i(index, clients_array);
-    if name_i.is_some() {
-        let name = name_i.unwrap(); // TODO: unwrap() -> ?
-        let name_str = std::str::from_utf8(&name[..]).unwrap(); // TODO: unwrap -> ?
-        println!("{} has left the chat", &name_str);
-        let mut leave_msg: String = String::new();
-        leave_msg.push_str(&name_str);
-        leave_msg.push_str(" has left the chat");
-        broadcast_msg_to_other_names(leave_msg.as_bytes(), index, clients_array, stream_array);
+    if let Some(name) = name_i {
+        log_client_event(Level::Info, "leave", index, clients_array, state, &[]);
+        state.run_hooks(|hook| hook.on_leave(&name));
+        let leave_msg = state
+            .with_client(index, |info| info.leave_notice.take())
+            .flatten()
+            .unwrap_or_else(|| format!("{} has left the chat", name));
+        broadcast_msg_to_other_names(
+            leave_msg.as_bytes(),
+            index,
+            clients_array,
+            stream_array,
+            state,
+        );
         remove_client_i(index, clients_array, stream_array);
-        Err(ClientLeavedError::new(name_str))
+        Err(ClientLeavedError::new(&name))
     } else {
+        if stream_array.lock().unwrap()[index].is_some() {
+            log_client_event(Level::Info, "disconnect", index, clients_array, state, &[]);
+        }
         remove_client_i(index, clients_array, stream_array);
         Ok(())
     }
@@ -99,45 +278,561 @@ pub fn handle_version(
     index: usize,
     clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
+    state: &SharedState,
 ) {
-    let name_array_clone = Arc::clone(clients_array);
-    let name_arrays_mutex = name_array_clone.lock().unwrap();
-    let name_arrays: [Option<[u8; MAX_NAME_LEN]>; MAX_CLIENTS] = *name_arrays_mutex;
-    if name_arrays[index].is_some() {
+    if is_user_registered(index, clients_array) {
         // send version
-        println!(
-            "{}",
-            str::from_utf8(VERSION)
-                .unwrap()
-                .to_string()
-                .trim_matches(char::from(0))
-        );
-        send_msg_to_ith_client(VERSION, index, &name_array_clone, clients_streams)
+        send_msg_to_ith_client(VERSION, index, clients_array, clients_streams, state)
+    }
+}
+
+// MOTD command: sends the message of the day again.
+pub fn handle_motd(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    if !send_motd(index, clients_array, clients_streams, state) {
+        let msg = b">>> there is no message of the day";
+        send_msg_to_ith_client(msg, index, clients_array, clients_streams, state);
+    }
+}
+
+// send the message of the day to the ith client. Returns false if there is
+// none.
+pub fn send_motd(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> bool {
+    let lines = state.motd.lock().unwrap().lines().to_vec();
+    if lines.is_empty() {
+        return false;
     }
+    let mut motd = String::from(">>> message of the day:");
+    for line in lines {
+        motd.push_str("\n>>> ");
+        motd.push_str(&line);
+    }
+    send_msg_to_ith_client(
+        motd.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+    true
 }
 
-// WHO command: list registered participans
+// greet a new connection, before it JOINs the chat.
+pub fn send_connect_banner(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let banner = format!(
+        ">>> welcome to {}>>> use JOIN <name> to enter the chat",
+        str::from_utf8(VERSION).unwrap()
+    );
+    send_msg_to_ith_client(
+        banner.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+    send_motd(index, clients_array, clients_streams, state);
+}
+
+// WHO [pattern] command: list registered participans, with their presence,
+// idle time and how long ago they joined. The pattern filters the names, i.e.:
+// `WHO a*`. The list is sent as a single response, i.e.:
+//   >>> WHO BEGIN
+//   alice                active idle 4s     joined 35m10s ago
+//   >>> WHO END 1
 pub fn handle_who(
+    input: &str,
     index: usize,
     clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
+    state: &SharedState,
 ) {
     if is_user_registered(index, clients_array) {
-        let name_array_clone = Arc::clone(clients_array);
-        let name_arrays_mutex = name_array_clone.lock().unwrap();
-        let name_arrays: [Option<[u8; MAX_NAME_LEN]>; MAX_CLIENTS] = *name_arrays_mutex;
-        for name in name_arrays {
-            if name.is_some() {
-                println!(
-                    "{}",
-                    str::from_utf8(&name.unwrap())
-                        .unwrap()
-                        .to_string()
-                        .trim_matches(char::from(0))
-                );
-                send_msg_to_ith_client(&name.unwrap(), index, &name_array_clone, clients_streams)
-            }
+        let (_, pattern) = first_2_words(input);
+        let now = Instant::now();
+        let idle_after = Duration::from_secs(state.config().idle_secs);
+        let name_arrays = clients_array.lock().unwrap().clone();
+        let clients_info = state.clients_info.lock().unwrap().clone();
+
+        let lines: Vec<String> = name_arrays
+            .iter()
+            .zip(clients_info.iter())
+            .filter_map(|(name, info)| Some((name.as_ref()?, info.as_ref()?)))
+            .filter(|(name, _)| pattern.is_none_or(|pattern| name_matches(pattern, name)))
+            .map(|(name, info)| who_line(name, info, now, idle_after))
+            .collect();
+        let response = framed_response("WHO", &lines);
+        send_msg_to_ith_client(
+            response.as_bytes(),
+            index,
+            clients_array,
+            clients_streams,
+            state,
+        )
+    }
+}
+
+// frame the lines of a listing (i.e.: WHO), so the end of the list and the
+// number of entries are known.
+pub fn framed_response(name: &str, lines: &[String]) -> String {
+    let mut response = format!(">>> {} BEGIN\n", name);
+    for line in lines {
+        response.push_str(line);
+        response.push('\n');
+    }
+    response.push_str(&format!(">>> {} END {}", name, lines.len()));
+    response
+}
+
+// a command of the server, as listed by HELP.
+pub struct BuiltinCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub operator_only: bool,
+}
+
+const fn builtin(
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    operator_only: bool,
+) -> BuiltinCommand {
+    BuiltinCommand {
+        name,
+        usage,
+        help,
+        operator_only,
+    }
+}
+
+pub const BUILTIN_COMMANDS: &[BuiltinCommand] = &[
+    builtin("JOIN", "JOIN <name>", "enter the chat", false),
+    builtin("LEAVE", "LEAVE", "leave the chat", false),
+    builtin("WHO", "WHO [pattern]", "list the users", false),
+    builtin("WHOIS", "WHOIS <nick>", "show the details of a user", false),
+    builtin("MSG", "MSG <nick> <text>", "send a private message", false),
+    builtin(
+        "SAY",
+        "SAY <text>",
+        "send a message, answered with OK or the reason it is refused",
+        false,
+    ),
+    builtin(
+        "AWAY",
+        "AWAY [message]",
+        "tell the others you are away",
+        false,
+    ),
+    builtin("BACK", "BACK", "tell the others you are back", false),
+    builtin("MOTD", "MOTD", "show the message of the day", false),
+    builtin(
+        "VERSION",
+        "VERSION",
+        "show the version of the server",
+        false,
+    ),
+    builtin("HELP", "HELP", "list the commands", false),
+    builtin("OPER", "OPER <password>", "become an operator", false),
+    builtin("KICK", "KICK <nick> [reason]", "disconnect a user", true),
+    builtin(
+        "MUTE",
+        "MUTE <nick> [duration]",
+        "stop a user from talking",
+        true,
+    ),
+    builtin(
+        "UNMUTE",
+        "UNMUTE <nick>",
+        "let a muted user talk again",
+        true,
+    ),
+    builtin(
+        "BAN",
+        "BAN <pattern|nick> [duration] [reason]",
+        "ban a nick, an address or a network",
+        true,
+    ),
+    builtin(
+        "ANNOUNCE",
+        "ANNOUNCE <text>",
+        "send a notice to everybody",
+        true,
+    ),
+];
+
+// HELP command: list the commands the client can use, the custom ones
+// included. Operator commands are only listed to operators.
+pub fn handle_help(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let operator = state.is_operator(index);
+    let mut lines: Vec<String> = BUILTIN_COMMANDS
+        .iter()
+        .filter(|command| operator || !command.operator_only)
+        .map(|command| help_line(command.usage, command.help))
+        .collect();
+    lines.extend(
+        state
+            .commands
+            .read()
+            .unwrap()
+            .commands()
+            .filter(|command| operator || !command.operator_only())
+            .map(|command| help_line(command.usage(), command.help())),
+    );
+    let response = framed_response("HELP", &lines);
+    send_msg_to_ith_client(
+        response.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+}
+
+fn help_line(usage: &str, help: &str) -> String {
+    format!("{:<40} {}", usage, help)
+}
+
+// run a command registered from outside the crate.
+pub fn handle_custom_command(
+    handler: &dyn CommandHandler,
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    if handler.requires_join() && !is_user_registered(index, clients_array) {
+        let msg = format!(">>> JOIN the chat before using {}", handler.name());
+        send_msg_to_ith_client(msg.as_bytes(), index, clients_array, clients_streams, state);
+        return;
+    }
+    if handler.operator_only() && !require_operator(index, clients_array, clients_streams, state) {
+        return;
+    }
+
+    let args = input
+        .trim()
+        .strip_prefix(handler.name())
+        .unwrap_or("")
+        .trim();
+    let context = CommandContext {
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    };
+    handler.handle(args, &context);
+}
+
+// one line of the WHO listing, i.e.:
+//   alice                away   idle 12m03s joined 1h20m ago (lunch)
+pub fn who_line(name: &str, info: &ClientInfo, now: Instant, idle_after: Duration) -> String {
+    let presence = info.presence(now, idle_after);
+    let mut line = format!(
+        "{:<20} {:<6} idle {:<6} joined {} ago",
+        name,
+        presence,
+        format_duration(now - info.last_activity),
+        format_duration(now - info.joined_at.unwrap_or(info.connected_at))
+    );
+    if let Presence::Away(message) = presence {
+        if !message.is_empty() {
+            line.push_str(&format!(" ({})", message));
+        }
+    }
+    line
+}
+
+// WHOIS <nick>: details of a user. The address and position of the client
+// are only shown to operators.
+pub fn handle_whois(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    if !is_user_registered(index, clients_array) {
+        return;
+    }
+    let reply = match first_2_words(input) {
+        (_, Some(nick)) => match find_client_by_name(nick, clients_array) {
+            Some(target) => match state.with_client(target, |info| info.clone()) {
+                Some(info) => whois_lines(
+                    nick,
+                    target,
+                    &info,
+                    Instant::now(),
+                    Duration::from_secs(state.config().idle_secs),
+                    state.is_operator(index),
+                ),
+                None => format!(">>> there is no user named {}", nick),
+            },
+            None => format!(">>> there is no user named {}", nick),
+        },
+        _ => String::from(">>> usage: WHOIS <nick>"),
+    };
+    send_msg_to_ith_client(
+        reply.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+}
+
+// the WHOIS reply, one detail per line, i.e.:
+//   >>> alice
+//   >>>   connected since 2024-05-01T10:20:30Z (1h20m ago)
+//   >>>   joined 1h19m ago
+//   >>>   rooms: main
+//   >>>   idle 12m03s
+//   >>>   away: lunch
+pub fn whois_lines(
+    nick: &str,
+    position: usize,
+    info: &ClientInfo,
+    now: Instant,
+    idle_after: Duration,
+    show_address: bool,
+) -> String {
+    let connected_for = now - info.connected_at;
+    let mut lines = vec![
+        nick.to_string(),
+        format!(
+            "  connected since {} ({} ago)",
+            logger::format_timestamp(SystemTime::now() - connected_for),
+            format_duration(connected_for)
+        ),
+    ];
+    if let Some(joined_at) = info.joined_at {
+        lines.push(format!("  joined {} ago", format_duration(now - joined_at)));
+        lines.push(format!("  rooms: {}", ROOM));
+    }
+    lines.push(format!(
+        "  idle {}",
+        format_duration(now - info.last_activity)
+    ));
+    match info.presence(now, idle_after) {
+        Presence::Away(message) if !message.is_empty() => {
+            lines.push(format!("  away: {}", message))
+        }
+        presence => lines.push(format!("  {}", presence)),
+    }
+    if info.operator {
+        lines.push(String::from("  is an operator"));
+    }
+    if show_address {
+        lines.push(format!("  address {}", info.address));
+        lines.push(format!("  position {}", position));
+    }
+    lines
+        .iter()
+        .map(|line| format!(">>> {}", line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// AWAY [message]: marks the user as away until BACK, and tells the others.
+pub fn handle_away(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let name = match get_client_name_at_position_i(index, clients_array) {
+        Some(name) => name,
+        None => return,
+    };
+    let message = input.trim().strip_prefix("AWAY").unwrap_or("").trim();
+    state.with_client(index, |info| info.away = Some(message.to_string()));
+    log_client_event(
+        Level::Info,
+        "away",
+        index,
+        clients_array,
+        state,
+        &[("message", &message)],
+    );
+
+    let notice = if message.is_empty() {
+        format!("{} is away", name)
+    } else {
+        format!("{} is away: {}", name, message)
+    };
+    broadcast_msg_to_other_names(
+        notice.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+    send_msg_to_ith_client(
+        b">>> you are marked as away, use BACK when you return",
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+}
+
+// BACK: the user is not away anymore.
+pub fn handle_back(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let name = match get_client_name_at_position_i(index, clients_array) {
+        Some(name) => name,
+        None => return,
+    };
+    let was_away = state
+        .with_client(index, |info| info.away.take())
+        .flatten()
+        .is_some();
+    if !was_away {
+        let msg = b">>> you are not away";
+        send_msg_to_ith_client(msg, index, clients_array, clients_streams, state);
+        return;
+    }
+
+    log_client_event(Level::Info, "back", index, clients_array, state, &[]);
+    let notice = format!("{} is back", name);
+    broadcast_msg_to_other_names(
+        notice.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+    let msg = b">>> welcome back";
+    send_msg_to_ith_client(msg, index, clients_array, clients_streams, state);
+}
+
+// SAY <text>: a message to the chat that is always answered, for the bots:
+// `>>> SAY OK` once it is sent, or `>>> SAY REFUSED <reason>`. The text is
+// sent as it is, even if it starts like a command.
+pub fn handle_say(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let text = input.trim_start().strip_prefix("SAY").unwrap_or("");
+    let text = text.strip_prefix([' ', '\t']).unwrap_or(text);
+    let reply = if !is_user_registered(index, clients_array) {
+        say_refused("join the chat first")
+    } else if text.trim().is_empty() {
+        say_refused("usage: SAY <text>")
+    } else {
+        match broadcast(
+            text.as_bytes(),
+            index,
+            clients_array,
+            clients_streams,
+            state,
+        ) {
+            Ok(()) => String::from(">>> SAY OK"),
+            Err(reason) => say_refused(&reason),
+        }
+    };
+    send_msg_to_ith_client(
+        reply.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+}
+
+// the answer to a SAY that is not sent. A veto without a reason, silent for
+// other messages, gets one here.
+fn say_refused(reason: &str) -> String {
+    match reason {
+        "" => String::from(">>> SAY REFUSED the message was refused"),
+        reason => format!(">>> SAY REFUSED {}", reason),
+    }
+}
+
+// MSG <nick> <text>: sends a private message to a user. If the user is away,
+// the sender gets its away message as an automatic reply.
+pub fn handle_msg(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let sender = match get_client_name_at_position_i(index, clients_array) {
+        Some(name) => name,
+        None => return,
+    };
+    let args = input.trim().strip_prefix("MSG").unwrap_or("").trim_start();
+    let (nick, text) = args
+        .split_once(char::is_whitespace)
+        .map(|(nick, text)| (nick, text.trim()))
+        .unwrap_or((args, ""));
+    if nick.is_empty() || text.is_empty() {
+        let msg = b">>> usage: MSG <nick> <text>";
+        send_msg_to_ith_client(msg, index, clients_array, clients_streams, state);
+        return;
+    }
+
+    let target = match find_client_by_name(nick, clients_array) {
+        Some(target) => target,
+        None => {
+            let msg = format!(">>> there is no user named {}", nick);
+            send_msg_to_ith_client(msg.as_bytes(), index, clients_array, clients_streams, state);
+            return;
         }
+    };
+    let msg = format!("[{} (private)] {}", sender, text);
+    send_msg_to_ith_client(
+        msg.as_bytes(),
+        target,
+        clients_array,
+        clients_streams,
+        state,
+    );
+
+    if let Some(message) = state
+        .with_client(target, |info| info.away.clone())
+        .flatten()
+    {
+        let reply = if message.is_empty() {
+            format!(">>> {} is away", nick)
+        } else {
+            format!(">>> {} is away: {}", nick, message)
+        };
+        send_msg_to_ith_client(
+            reply.as_bytes(),
+            index,
+            clients_array,
+            clients_streams,
+            state,
+        );
     }
 }
 
@@ -146,39 +841,106 @@ pub fn handle_join(
     index: usize,
     clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
+    state: &SharedState,
 ) {
     if !is_user_registered(index, clients_array) {
-        let (_, name) = first_2_words(std::str::from_utf8(input).unwrap());
-        if name.is_some() {
-            let name = name.unwrap();
-
-            // TODO: check if the name already exists in the names_array.
-
-            // copy the name to the client's name array
-            let mut client_name: [u8; MAX_NAME_LEN] = [0; MAX_NAME_LEN];
-            let mut i: usize = 0;
-            // copy the bytes into the name
-            while i < MAX_NAME_LEN && i < name.as_bytes().len() {
-                client_name[i] = name.as_bytes()[i];
-                i += 1;
+        let input = String::from_utf8_lossy(input);
+        let (_, name) = first_2_words(&input);
+        if let Some(name) = name {
+            let name = match validate_name(name) {
+                Ok(name) => name,
+                Err(error) => {
+                    let msg = format!(">>> invalid name: {}", error);
+                    send_msg_to_ith_client(
+                        msg.as_bytes(),
+                        index,
+                        clients_array,
+                        clients_streams,
+                        state,
+                    );
+                    return;
+                }
+            };
+            let name = name.as_str();
+
+            let ban = state
+                .bans
+                .lock()
+                .unwrap()
+                .find_nick(name, SystemTime::now())
+                .cloned();
+            if let Some(ban) = ban {
+                log_client_event(
+                    Level::Warn,
+                    "join_refused",
+                    index,
+                    clients_array,
+                    state,
+                    &[("name", &name), ("ban", &ban)],
+                );
+                let msg = format!(">>> the name {} is banned from this server ({})", name, ban);
+                send_msg_to_ith_client(
+                    msg.as_bytes(),
+                    index,
+                    clients_array,
+                    clients_streams,
+                    state,
+                );
+                return;
             }
 
-            {
-                let clients = Arc::clone(clients_array);
-                let mut array_clients = clients.lock().unwrap();
-                array_clients[index] = Some(client_name);
+            // the check and the update are done under the same lock, so two
+            // clients cannot take the same name at once
+            let taken = {
+                let mut array_clients = clients_array.lock().unwrap();
+                let taken = array_clients
+                    .iter()
+                    .flatten()
+                    .any(|other| same_name(other, name));
+                if !taken {
+                    array_clients[index] = Some(name.to_string());
+                }
+                taken
+            };
+            if taken {
+                let msg = format!(">>> the name {} is already in use", name);
+                send_msg_to_ith_client(
+                    msg.as_bytes(),
+                    index,
+                    clients_array,
+                    clients_streams,
+                    state,
+                );
+                return;
             }
 
-            println!("{} has joined the chat", name);
-            let join_msg = format!(
-                "{} has joined the chat",
-                str::from_utf8(&client_name).unwrap()
-            );
+            state.with_client(index, |info| info.joined_at = Some(Instant::now()));
+            log_client_event(Level::Info, "join", index, clients_array, state, &[]);
+            state.run_hooks(|hook| hook.on_join(name));
+            let join_msg = format!("{} has joined the chat", name);
             broadcast_msg_to_other_names(
                 join_msg.as_bytes(),
                 index,
                 clients_array,
                 clients_streams,
+                state,
+            );
+
+            let users = clients_array.lock().unwrap().iter().flatten().count();
+            let welcome = format!(
+                ">>> welcome to the chat, {}! {}",
+                name,
+                match users {
+                    1 => String::from("you are the only user here"),
+                    users => format!("there are {} users here", users),
+                }
+            );
+            send_msg_to_ith_client(
+                welcome.as_bytes(),
+                index,
+                clients_array,
+                clients_streams,
+                state,
             );
         }
     } else {
@@ -188,40 +950,414 @@ pub fn handle_join(
     }
 }
 
+// OPER command: grants the operator role to a client giving the right password.
+pub fn handle_oper(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let (_, password) = first_2_words(input);
+    let reply: &[u8] = match (&state.config().oper_password, password) {
+        (None, _) => b">>> OPER is disabled in this server",
+        (Some(expected), Some(password))
+            if constant_time_eq(expected.as_bytes(), password.as_bytes()) =>
+        {
+            state.with_client(index, |info| info.operator = true);
+            b">>> you are now an operator"
+        }
+        _ => b">>> wrong password",
+    };
+    let granted = state.is_operator(index);
+    log_client_event(
+        if granted { Level::Info } else { Level::Warn },
+        "oper",
+        index,
+        clients_array,
+        state,
+        &[("granted", &granted)],
+    );
+    send_msg_to_ith_client(reply, index, clients_array, clients_streams, state);
+}
+
+// check the client is an operator, and tell it otherwise.
+pub fn require_operator(
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> bool {
+    if !state.is_operator(index) {
+        send_msg_to_ith_client(
+            b">>> permission denied: you are not an operator",
+            index,
+            clients_array,
+            clients_streams,
+            state,
+        );
+        return false;
+    }
+    true
+}
+
+// the commands reserved to operators:
+//   KICK <nick> [reason]             disconnect a user
+//   MUTE <nick> [duration]           stop a user from sending messages
+//   UNMUTE <nick>                    allow a muted user to talk again
+//   BAN <pattern|nick> [duration] [reason]
+//   ANNOUNCE <text>                  send a notice to everybody
+pub fn handle_operator_command(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let operator = get_client_name_at_position_i(index, clients_array)
+        .unwrap_or_else(|| String::from("an operator"));
+    let words: Vec<&str> = input.split_ascii_whitespace().collect();
+    let args = &words[1..];
+
+    let reply = match words[0] {
+        "KICK" => handle_kick(args, &operator, clients_array, clients_streams, state),
+        "MUTE" => handle_mute(args, &operator, true, clients_array, clients_streams, state),
+        "UNMUTE" => handle_mute(
+            args,
+            &operator,
+            false,
+            clients_array,
+            clients_streams,
+            state,
+        ),
+        "BAN" => handle_ban(args, clients_array, clients_streams, state),
+        _ => handle_announce(input, index, clients_array, clients_streams, state),
+    };
+    log_client_event(
+        Level::Info,
+        "operator_command",
+        index,
+        clients_array,
+        state,
+        &[("command", &input.trim()), ("result", &reply)],
+    );
+    send_msg_to_ith_client(
+        reply.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+}
+
+// KICK <nick> [reason]
+pub fn handle_kick(
+    args: &[&str],
+    operator: &str,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let target = match args.first() {
+        Some(nick) => *nick,
+        None => return String::from(">>> usage: KICK <nick> [reason]"),
+    };
+    let target_index = match find_client_by_name(target, clients_array) {
+        Some(target_index) => target_index,
+        None => return format!(">>> there is no user called {}", target),
+    };
+
+    let reason = if args.len() > 1 {
+        format!(" ({})", args[1..].join(" "))
+    } else {
+        String::new()
+    };
+    let kick_msg = format!("{} was kicked by {}{}", target, operator, reason);
+    log_client_event(
+        Level::Info,
+        "kick",
+        target_index,
+        clients_array,
+        state,
+        &[("by", &operator), ("reason", &reason.trim())],
+    );
+
+    // the thread of the kicked client tells the others when it sees the
+    // connection closed, and frees its slot
+    let id = state.with_client(target_index, |info| {
+        info.leave_notice = Some(kick_msg);
+        info.id
+    });
+    let notice = format!(">>> you have been kicked by {}{}", operator, reason);
+    match id {
+        Some(id) if close_connection(target_index, id, &notice, clients_streams, state) => {
+            format!(">>> {} kicked", target)
+        }
+        _ => format!(">>> there is no user called {}", target),
+    }
+}
+
+// MUTE <nick> [duration] and UNMUTE <nick>
+fn handle_mute(
+    args: &[&str],
+    operator: &str,
+    mute: bool,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let target = match args.first() {
+        Some(nick) => *nick,
+        None if mute => return String::from(">>> usage: MUTE <nick> [duration]"),
+        None => return String::from(">>> usage: UNMUTE <nick>"),
+    };
+    let target_index = match find_client_by_name(target, clients_array) {
+        Some(target_index) => target_index,
+        None => return format!(">>> there is no user called {}", target),
+    };
+
+    let (new_mute, notice) = if !mute {
+        (None, format!(">>> you have been unmuted by {}", operator))
+    } else {
+        match args.get(1) {
+            None => (
+                Some(Mute::Indefinitely),
+                format!(">>> you have been muted by {}", operator),
+            ),
+            Some(duration) => match parse_duration(duration) {
+                Some(duration) => (
+                    Some(Mute::Until(Instant::now() + duration)),
+                    format!(
+                        ">>> you have been muted by {} for {} seconds",
+                        operator,
+                        duration.as_secs()
+                    ),
+                ),
+                None => return format!(">>> invalid duration: {}", duration),
+            },
+        }
+    };
+
+    state.with_client(target_index, |info| info.mute = new_mute);
+    send_msg_to_ith_client(notice.as_bytes(), target_index, clients_array, clients_streams, state);
+    if mute {
+        format!(">>> {} muted", target)
+    } else {
+        format!(">>> {} unmuted", target)
+    }
+}
+
+// BAN <pattern|nick> [duration] [reason]
+// a plain name is banned as the `nick:<name>` pattern.
+fn handle_ban(
+    args: &[&str],
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    if let Some(pattern) = args.first_mut() {
+        if BanPattern::parse(pattern).is_none() {
+            *pattern = format!("nick:{}", pattern);
+        }
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    format!(">>> {}", admin_ban(&args, clients_array, clients_streams, state))
+}
+
+// ANNOUNCE <text>
+pub fn handle_announce(
+    input: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let text = input.trim().strip_prefix("ANNOUNCE").unwrap_or("").trim();
+    if text.is_empty() {
+        return String::from(">>> usage: ANNOUNCE <text>");
+    }
+    let announcement = format!("*** ANNOUNCEMENT: {}", text);
+    logger::info("announce", &[("text", &text)]);
+    broadcast_msg_to_other_names(
+        announcement.as_bytes(),
+        index,
+        clients_array,
+        clients_streams,
+        state,
+    );
+    announcement
+}
+
 // handle the chat client. This funtion will run in a independent thread,
 // will check the incomming messages, check for the different commads, and
 // execute those commands.
 pub fn handle_client(
-    mut stream: TcpStream,
+    stream: TcpStream,
     index: usize,
     clients_array: &ClientsNameArray,
     stream_array: &ClientsStreamArray,
+    state: &SharedState,
 ) -> Result<(), ClientLeavedError> {
-    let mut data = [0_u8; MAX_MESSAGE_SIZE]; // using 512 byte buffer
+    let mut reader = BufReader::new(stream);
+    let mut data: Vec<u8> = Vec::with_capacity(MAX_MESSAGE_SIZE);
+    let mut flood_guard = FloodGuard::new(&state.config(), Instant::now());
+    send_connect_banner(index, clients_array, stream_array, state);
     loop {
-        let size = stream
-            .read(&mut data)
-            .expect("error when reading the stream");
+        // read one line. Lines longer than MAX_MESSAGE_SIZE are split, and
+        // processed as several messages.
+        data.clear();
+        let size = (&mut reader)
+            .take(MAX_MESSAGE_SIZE as u64)
+            .read_until(b'\n', &mut data)
+            .unwrap_or(0);
 
-        // TODO: FIXME: what happens when size is bigger than MAX_MESSAGE_SIZE?
+        if size == 0 {
+            // the client closed the connection (or it failed)
+            return handle_leave(index, clients_array, stream_array, state);
+        }
 
-        server_chat_output(&data, index, size, clients_array);
+        // the lines dropped here are answered too when they are a SAY
+        let line = String::from_utf8_lossy(&data).into_owned();
+        let refusal = |reason: &str| {
+            if check_say(&line) {
+                say_refused(reason)
+            } else {
+                format!(">>> {}", reason)
+            }
+        };
 
-        handle_commands(&data, index, clients_array, stream_array)?;
+        let now = Instant::now();
+        ServerStats::increment(&state.stats.messages_received);
+        ServerStats::add(&state.stats.bytes_received, size);
+        state.with_client(index, |info| info.last_activity = now);
 
-        data = [0; MAX_MESSAGE_SIZE]; // clean the buffer for the next iter
+        match flood_guard.check(size, now) {
+            FloodVerdict::Allow => {}
+            FloodVerdict::Warn => {
+                log_client_event(
+                    Level::Warn,
+                    "flood_warning",
+                    index,
+                    clients_array,
+                    state,
+                    &[],
+                );
+                let warning = refusal("you are sending messages too fast, slow down");
+                send_msg_to_ith_client(
+                    warning.as_bytes(),
+                    index,
+                    clients_array,
+                    stream_array,
+                    state,
+                );
+                continue;
+            }
+            FloodVerdict::Mute(duration) => {
+                log_client_event(
+                    Level::Warn,
+                    "flood_mute",
+                    index,
+                    clients_array,
+                    state,
+                    &[("secs", &duration.as_secs())],
+                );
+                let warning = refusal(&format!(
+                    "flood detected: you are muted for {} seconds",
+                    duration.as_secs()
+                ));
+                send_msg_to_ith_client(
+                    warning.as_bytes(),
+                    index,
+                    clients_array,
+                    stream_array,
+                    state,
+                );
+                continue;
+            }
+            FloodVerdict::Disconnect => {
+                log_client_event(
+                    Level::Warn,
+                    "flood_disconnect",
+                    index,
+                    clients_array,
+                    state,
+                    &[],
+                );
+                let warning = b">>> flood detected: disconnected";
+                send_msg_to_ith_client(warning, index, clients_array, stream_array, state);
+                return handle_leave(index, clients_array, stream_array, state);
+            }
+        }
+
+        let muted_for = flood_guard.muted_for(now).or_else(|| {
+            state
+                .with_client(index, |info| info.muted_for(now))
+                .flatten()
+        });
+        if let Some(remaining) = muted_for {
+            // private messages and SAY are chat too
+            if !is_any_command(&data, state) || check_msg(&line) || check_say(&line) {
+                let warning = if remaining == Duration::MAX {
+                    refusal("you are muted")
+                } else {
+                    refusal(&format!(
+                        "you are muted for {} more seconds",
+                        remaining.as_secs() + 1
+                    ))
+                };
+                send_msg_to_ith_client(
+                    warning.as_bytes(),
+                    index,
+                    clients_array,
+                    stream_array,
+                    state,
+                );
+                continue;
+            }
+        }
+
+        server_chat_output(&data, index, size, clients_array, state);
+
+        handle_commands(&data, index, clients_array, stream_array, state)?;
     }
 }
 
 // send a given message to all the other chat clients except for the
 // one who send the message.
+//
+// It fails with the reason of the hook that vetoed the message, if any.
 pub fn broadcast(
     message: &[u8],
     index: usize,
     clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
-) {
-    if is_user_registered(index, clients_array) {
+    state: &SharedState,
+) -> Result<(), String> {
+    if let Some(name) = get_client_name_at_position_i(index, clients_array) {
+        let text = String::from_utf8_lossy(message);
+        // the hooks see the text without the line break, which is kept
+        let (text, line_break) = text.split_at(text.trim_end().len());
+        let text = match state.filter_through_hooks(text, |hook, text| hook.on_message(&name, text))
+        {
+            Ok(text) => text,
+            Err(reason) => {
+                log_client_event(
+                    Level::Info,
+                    "message_vetoed",
+                    index,
+                    clients_array,
+                    state,
+                    &[("reason", &reason)],
+                );
+                return Err(reason);
+            }
+        };
+        ServerStats::increment(&state.stats.messages_broadcast);
+        if let Some(webhook) = &state.webhook {
+            webhook.post(&name, &text);
+        }
+        let msg = format!("[{}] {}{}", name, text, line_break);
         // TODO: instead of iterate over MAX_CLIENTS iterate over the array
         // i.e.: for (i, &name) in clients_arrays.iter().enumerate()? // iter_into()?
         for i in 0..MAX_CLIENTS {
@@ -238,20 +1374,29 @@ pub fn broadcast(
                     .try_clone()
                     .expect("failed to clone a stream");
 
-                let name_i = get_client_name_at_position_i(index, clients_array);
-                if name_i.is_some() {
-                    let msg = format!(
-                        "[{}] {}",
-                        str::from_utf8(&name_i.unwrap()[..]).unwrap(),
-                        str::from_utf8(message).unwrap()
-                    );
-                    stream_i
-                        .write_all(msg.as_bytes())
-                        .expect("Failed to send data through a stream");
+                match stream_i.write_all(msg.as_bytes()) {
+                    Ok(()) => ServerStats::add(&state.stats.bytes_sent, msg.len()),
+                    Err(error) => logger::debug("send_failed", &[("pos", &i), ("error", &error)]),
                 }
             }
         }
     }
+    Ok(())
+}
+
+// tell a client that a hook stopped its message or command. An empty reason
+// drops it silently (but not a SAY, see `say_refused`).
+fn send_veto(
+    reason: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    if !reason.is_empty() {
+        let msg = format!(">>> {}", reason);
+        send_msg_to_ith_client(msg.as_bytes(), index, clients_array, clients_streams, state);
+    }
 }
 
 // remove client at ith position, and shut down socket connection if needed
@@ -265,20 +1410,80 @@ pub fn remove_client_i(
     array_clients[index] = None;
     let stream = Arc::clone(stream_array);
     let mut stream_client = stream.lock().unwrap();
-    stream_client[index]
-        .as_mut()
+    if let Some(stream) = stream_client[index].take() {
+        // the other end may be already gone, in that case there is nothing to shut down.
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+// send a last message to the ith client and close its connection, from
+// another thread. The slot is not freed here: the thread of the client sees
+// the connection closing and removes it from the chat, and until then the
+// slot cannot be given to a new connection. The id makes sure it is still
+// the connection meant, and not a new one that took the slot meanwhile.
+// It returns whether the connection was found.
+pub fn close_connection(
+    index: usize,
+    id: u64,
+    reason: &str,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> bool {
+    // the info is locked first, like everywhere else, so its id cannot
+    // change while the stream is closed
+    let info = state.clients_info.lock().unwrap();
+    if info[index].as_ref().map(|info| info.id) != Some(id) {
+        return false;
+    }
+    let streams = stream_array.lock().unwrap();
+    let stream = match streams[index].as_ref() {
+        Some(stream) => stream,
+        None => return false,
+    };
+    let mut writer = stream;
+    if writer.write_all(format!("{}\n", reason).as_bytes()).is_ok() {
+        ServerStats::add(&state.stats.bytes_sent, reason.len() + 1);
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    true
+}
+
+// count how many of the current connections come from the given address.
+pub fn count_connections_from(
+    address: IpAddr,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> usize {
+    let open: Vec<bool> = stream_array
+        .lock()
+        .unwrap()
+        .iter()
+        .map(Option::is_some)
+        .collect();
+    // the address the client connected from: for the WebSocket users it is
+    // not the one of their stream (see `websocket`)
+    state
+        .clients_info
+        .lock()
         .unwrap()
-        .shutdown(Shutdown::Both)
-        .expect("Unable to shutdown the stream");
-    stream_client[index] = None;
+        .iter()
+        .zip(open)
+        .filter(|(info, open)| {
+            *open
+                && info
+                    .as_ref()
+                    .is_some_and(|info| info.address.ip() == address)
+        })
+        .count()
 }
 
 // send a given message to the ith chat client.
 pub fn send_msg_to_ith_client(
     message: &[u8],
     index: usize,
-    clients_array: &ClientsNameArray,
+    _clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
+    state: &SharedState,
 ) {
     let clients_streams = Arc::clone(clients_streams);
     let mut stream_mutex = clients_streams.lock().unwrap();
@@ -289,12 +1494,15 @@ pub fn send_msg_to_ith_client(
             .unwrap()
             .try_clone()
             .expect("failed to clone a stream");
-        stream_i
+        // the client may be gone already: its own thread cleans up when it
+        // notices, so here the error is only logged.
+        let written = stream_i
             .write_all(message)
-            .expect("Failed to send data through a stream");
-        stream_i
-            .write_all(String::from('\n').as_bytes())
-            .expect("Failed endline");
+            .and_then(|_| stream_i.write_all(String::from('\n').as_bytes()));
+        match written {
+            Ok(()) => ServerStats::add(&state.stats.bytes_sent, message.len() + 1),
+            Err(error) => logger::debug("send_failed", &[("pos", &index), ("error", &error)]),
+        }
     }
 }
 
@@ -304,34 +1512,33 @@ pub fn broadcast_msg_to_other_names(
     client_index: usize,
     clients_array: &ClientsNameArray,
     clients_streams: &ClientsStreamArray,
+    state: &SharedState,
 ) {
-    let name_array_clone = Arc::clone(clients_array);
-    let name_arrays_mutex = name_array_clone.lock().unwrap();
-    let name_arrays: [Option<[u8; MAX_NAME_LEN]>; MAX_CLIENTS] = *name_arrays_mutex;
-    for (i, &name) in name_arrays.iter().enumerate() {
-        if name.is_some() && client_index != i {
-            println!(
-                "{}",
-                str::from_utf8(&name.unwrap())
-                    .unwrap()
-                    .to_string()
-                    .trim_matches(char::from(0))
-            );
-            send_msg_to_ith_client(message, i, &name_array_clone, clients_streams)
+    // the names are copied, so the lock is not held while sending
+    let name_arrays = clients_array.lock().unwrap().clone();
+    for (i, name) in name_arrays.iter().enumerate() {
+        if let Some(name) = name.as_ref().filter(|_| client_index != i) {
+            logger::trace("deliver", &[("pos", &i), ("nick", name)]);
+            send_msg_to_ith_client(message, i, clients_array, clients_streams, state)
         }
     }
 }
 
+// find the position of the client with the given name (see `same_name`).
+pub fn find_client_by_name(name: &str, clients_array: &ClientsNameArray) -> Option<usize> {
+    clients_array.lock().unwrap().iter().position(|client| {
+        client
+            .as_ref()
+            .is_some_and(|client| same_name(client, name))
+    })
+}
+
 // retrieve the name of the ith client from the array of names
 pub fn get_client_name_at_position_i(
     index: usize,
     clients_array: &ClientsNameArray,
-) -> Option<[u8; MAX_NAME_LEN]> {
-    let name_array_clone = Arc::clone(clients_array);
-    let name_array_mutex = name_array_clone.lock().unwrap();
-    let name_array: [Option<[u8; MAX_NAME_LEN]>; MAX_CLIENTS] = *name_array_mutex;
-    let name_i: Option<[u8; MAX_NAME_LEN]> = name_array[index];
-    name_i
+) -> Option<String> {
+    clients_array.lock().unwrap()[index].clone()
 }
 
 // check if the user at ith position is already registered
@@ -339,31 +1546,65 @@ pub fn is_user_registered(index: usize, clients_array: &ClientsNameArray) -> boo
     get_client_name_at_position_i(index, clients_array).is_some()
 }
 
-// repeat ith client's message inside the server.
+// log the ith client's message. Its content is only logged when `log_chat`
+// is enabled.
 pub fn server_chat_output(
     input: &[u8],
     index: usize,
     size: usize,
     clients_array: &ClientsNameArray,
+    state: &SharedState,
 ) {
-    let user_name: [u8; MAX_NAME_LEN] = Default::default();
-    {
-        let name_i = get_client_name_at_position_i(index, clients_array);
-        if name_i.is_some() {
-            let name = name_i.unwrap();
-            let user_name = name;
-            print!(
-                "[{}] ",
-                std::str::from_utf8(&user_name)
-                    .unwrap()
-                    .trim_matches(char::from(0)) // maybe it could be use also: String::from_utf8_lossy()
-            );
-        }
+    if is_any_command(input, state) {
+        return; // commands are logged by themselves
+    }
+    if logger::chat_enabled() {
+        let text = String::from_utf8_lossy(&input[0..size]);
+        log_client_event(
+            Level::Info,
+            "message",
+            index,
+            clients_array,
+            state,
+            &[("text", &text.trim_end())],
+        );
+    } else {
+        log_client_event(
+            Level::Debug,
+            "message",
+            index,
+            clients_array,
+            state,
+            &[("bytes", &size)],
+        );
     }
+}
 
-    std::io::stdout()
-        .write_all(&input[0..size])
-        .expect("Error writing to stdout");
+// log an event about the ith client, adding its connection id, address and
+// name to the fields. It must not be called while holding the names lock.
+pub fn log_client_event(
+    level: Level,
+    event: &str,
+    index: usize,
+    clients_array: &ClientsNameArray,
+    state: &SharedState,
+    fields: &[(&str, &dyn Display)],
+) {
+    if !logger::enabled(level) {
+        return;
+    }
+    let (conn, peer) = state
+        .with_client(index, |info| {
+            (info.id.to_string(), info.address.to_string())
+        })
+        .unwrap_or_else(|| (String::from("-"), String::from("-")));
+    let nick =
+        get_client_name_at_position_i(index, clients_array).unwrap_or_else(|| String::from("-"));
+
+    let mut all_fields: Vec<(&str, &dyn Display)> =
+        vec![("conn", &conn), ("peer", &peer), ("nick", &nick)];
+    all_fields.extend_from_slice(fields);
+    logger::log(level, event, &all_fields);
 }
 
 // reference: https://stevedonovan.github.io/rust-gentle-intro/6-error-handling.html
@@ -392,9 +1633,59 @@ impl OtherError for ClientLeavedError {
     }
 }
 
-
 #[cfg(test)]  // this is to only compile it in the 'test' configuration
 mod tests {  // test ususally are defined in a local module at the end of the file
 
     use super::*; //to include everything above in the file to test
+
+    #[test]
+    fn verify_is_command() {
+        assert!(is_command(b"JOIN Alice\n"));
+        assert!(is_command(b"  WHO\n"));
+        assert!(is_command(b"LEAVE"));
+        assert!(!is_command(b"hello WHO\n"));
+        assert!(is_command(b"AWAY lunch\n"));
+        assert!(is_command(b"MSG bob hi\n"));
+        assert!(is_command(b"MSG bob \xff\xfe\n"));
+    }
+
+    #[test]
+    fn verify_who_line() {
+        let mut info = ClientInfo::new(1, "127.0.0.1:5000".parse().unwrap(), false);
+        let now = info.connected_at + Duration::from_secs(125);
+        info.joined_at = Some(info.connected_at + Duration::from_secs(5));
+        let idle_after = Duration::from_secs(60);
+        assert_eq!(
+            who_line("alice", &info, now, idle_after),
+            "alice                idle   idle 2m05s  joined 2m00s ago"
+        );
+        info.away = Some(String::from("lunch"));
+        assert!(who_line("alice", &info, now, idle_after).ends_with("ago (lunch)"));
+    }
+
+    #[test]
+    fn verify_framed_response() {
+        assert_eq!(framed_response("WHO", &[]), ">>> WHO BEGIN\n>>> WHO END 0");
+        let lines = [String::from("alice"), String::from("bob")];
+        assert_eq!(
+            framed_response("WHO", &lines),
+            ">>> WHO BEGIN\nalice\nbob\n>>> WHO END 2"
+        );
+    }
+
+    #[test]
+    fn verify_whois_lines() {
+        let mut info = ClientInfo::new(1, "10.0.0.7:5000".parse().unwrap(), false);
+        let now = info.connected_at + Duration::from_secs(90);
+        info.away = Some(String::from("lunch"));
+        let lines = whois_lines("alice", 3, &info, now, Duration::ZERO, false);
+        assert!(lines.starts_with(">>> alice\n>>>   connected since "));
+        assert!(lines.ends_with("(1m30s ago)\n>>>   idle 1m30s\n>>>   away: lunch"));
+        assert!(!lines.contains("rooms")); // not joined yet
+        info.joined_at = Some(info.connected_at + Duration::from_secs(30));
+        let lines = whois_lines("alice", 3, &info, now, Duration::ZERO, false);
+        assert!(lines.contains("\n>>>   joined 1m00s ago\n>>>   rooms: main\n"));
+        let lines = whois_lines("alice", 3, &info, now, Duration::ZERO, true);
+        assert!(lines.ends_with("\n>>>   address 10.0.0.7:5000\n>>>   position 3"));
+    }
 }
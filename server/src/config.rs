@@ -0,0 +1,312 @@
+// server configuration.
+//
+// The configuration file is plain text, with one `key = value` setting per
+// line. Empty lines and lines starting with '#' are ignored, and any setting
+// not present in the file keeps its default value, i.e.:
+//
+//   # allow 10 messages per second, with bursts of up to 20
+//   rate_messages_per_sec = 10
+//   rate_message_burst = 20
+
+use crate::bans::BanPattern;
+use crate::logger::Level;
+use crate::webhook::WebhookUrl;
+
+use std::error::Error as OtherError;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // flood protection: sustained rate and burst size allowed for every
+    // connection, in messages and in bytes. A rate of 0 disables the limit.
+    pub rate_messages_per_sec: f64,
+    pub rate_message_burst: f64,
+    pub rate_bytes_per_sec: f64,
+    pub rate_byte_burst: f64,
+    // how many warnings a client gets before being muted, how long the mute
+    // lasts, and how many mutes are tolerated before disconnecting it.
+    pub warnings_before_mute: u32,
+    pub mute_secs: u64,
+    pub mutes_before_disconnect: u32,
+    // seconds without sending anything after which a user shows as idle in
+    // WHO (0 disables the idle detection).
+    pub idle_secs: u64,
+    // maximum simultaneous connections from the same address (0 for no limit).
+    pub max_connections_per_ip: usize,
+    // file where the ban list is kept. Without it, bans are lost on restart.
+    pub ban_file: Option<String>,
+    // file with the message of the day, sent to the clients when they
+    // connect. It is read again when it changes.
+    pub motd_file: Option<String>,
+    // password for the OPER command. Without it, OPER is disabled.
+    pub oper_password: Option<String>,
+    // clients connecting from these addresses or networks are operators
+    // from the start, i.e.: `operator_hosts = 127.0.0.1, 10.1.0.0/16`
+    pub operator_hosts: Vec<BanPattern>,
+    // address of the admin control socket. It must be a loopback address,
+    // i.e.: `admin_address = 127.0.0.1:1154`. Without it, there is no socket.
+    pub admin_address: Option<SocketAddr>,
+    // address of the HTTP endpoint serving `/metrics` for Prometheus, i.e.:
+    // `metrics_address = 127.0.0.1:9153`. Without it, there is no endpoint.
+    pub metrics_address: Option<SocketAddr>,
+    // address accepting WebSocket connections from the browsers, i.e.:
+    // `websocket_address = 0.0.0.0:8080`. Without it, only TCP is used.
+    pub websocket_address: Option<SocketAddr>,
+    // HTTP API to post messages and list the users, i.e.:
+    // `api_address = 127.0.0.1:1155`. It must be a loopback address, and
+    // the requests must carry the `api_token`, so both are needed.
+    pub api_address: Option<SocketAddr>,
+    pub api_token: Option<String>,
+    // outgoing webhook: URL the chat messages are POSTed to, and a pattern
+    // they must match to be sent (all of them without it), i.e.:
+    // `webhook_pattern = *deploy*`. The requests waiting to be sent are at
+    // most `webhook_queue`, and a failed one is tried `webhook_retries` more
+    // times. These settings are only read when the server starts.
+    pub webhook_url: Option<WebhookUrl>,
+    pub webhook_pattern: Option<String>,
+    pub webhook_queue: usize,
+    pub webhook_retries: u32,
+    // logging: verbosity (error, warn, info, debug or trace), file to append
+    // the log to instead of stderr, and whether to log the chat messages.
+    pub log_level: Level,
+    pub log_file: Option<String>,
+    pub log_chat: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            rate_messages_per_sec: 5.0,
+            rate_message_burst: 10.0,
+            rate_bytes_per_sec: 2048.0,
+            rate_byte_burst: 4096.0,
+            warnings_before_mute: 3,
+            mute_secs: 30,
+            mutes_before_disconnect: 3,
+            idle_secs: 300,
+            max_connections_per_ip: 5,
+            ban_file: None,
+            motd_file: None,
+            oper_password: None,
+            operator_hosts: Vec::new(),
+            admin_address: None,
+            metrics_address: None,
+            websocket_address: None,
+            api_address: None,
+            api_token: None,
+            webhook_url: None,
+            webhook_pattern: None,
+            webhook_queue: 100,
+            webhook_retries: 3,
+            log_level: Level::Info,
+            log_file: None,
+            log_chat: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    // read the configuration from a file.
+    pub fn from_file(path: &str) -> Result<ServerConfig, ConfigError> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| ConfigError::new(&format!("cannot read {}: {}", path, error)))?;
+        ServerConfig::parse(&text)
+    }
+
+    // parse the text of a configuration file.
+    pub fn parse(text: &str) -> Result<ServerConfig, ConfigError> {
+        let mut config = ServerConfig::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    return Err(ConfigError::new(&format!(
+                        "line {}: expected `key = value`",
+                        number + 1
+                    )))
+                }
+            };
+
+            config.set(key, value).map_err(|error| {
+                ConfigError::new(&format!("line {}: {}", number + 1, error))
+            })?;
+        }
+
+        Ok(config)
+    }
+
+    // set a single setting by name.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "rate_messages_per_sec" => self.rate_messages_per_sec = parse_value(key, value)?,
+            "rate_message_burst" => self.rate_message_burst = parse_value(key, value)?,
+            "rate_bytes_per_sec" => self.rate_bytes_per_sec = parse_value(key, value)?,
+            "rate_byte_burst" => self.rate_byte_burst = parse_value(key, value)?,
+            "warnings_before_mute" => self.warnings_before_mute = parse_value(key, value)?,
+            "mute_secs" => self.mute_secs = parse_value(key, value)?,
+            "mutes_before_disconnect" => self.mutes_before_disconnect = parse_value(key, value)?,
+            "idle_secs" => self.idle_secs = parse_value(key, value)?,
+            "max_connections_per_ip" => self.max_connections_per_ip = parse_value(key, value)?,
+            "ban_file" => self.ban_file = parse_path(value),
+            "motd_file" => self.motd_file = parse_path(value),
+            "oper_password" => self.oper_password = parse_path(value),
+            "operator_hosts" => self.operator_hosts = parse_hosts(key, value)?,
+            "admin_address" => self.admin_address = parse_loopback(key, value)?,
+            "metrics_address" => self.metrics_address = parse_address(key, value)?,
+            "websocket_address" => self.websocket_address = parse_address(key, value)?,
+            "api_address" => self.api_address = parse_loopback(key, value)?,
+            "api_token" => self.api_token = parse_path(value),
+            "webhook_url" => self.webhook_url = parse_url(key, value)?,
+            "webhook_pattern" => self.webhook_pattern = parse_path(value),
+            "webhook_queue" => self.webhook_queue = parse_positive(key, value)?,
+            "webhook_retries" => self.webhook_retries = parse_value(key, value)?,
+            "log_level" => self.log_level = parse_value(key, value)?,
+            "log_file" => self.log_file = parse_path(value),
+            "log_chat" => self.log_chat = parse_value(key, value)?,
+            _ => return Err(ConfigError::new(&format!("unknown setting `{}`", key))),
+        }
+        Ok(())
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::new(&format!("invalid value `{}` for `{}`", value, key)))
+}
+
+// a number that cannot be 0.
+fn parse_positive(key: &str, value: &str) -> Result<usize, ConfigError> {
+    match parse_value(key, value)? {
+        0 => Err(ConfigError::new(&format!("`{}` must be at least 1", key))),
+        number => Ok(number),
+    }
+}
+
+// comma separated list of addresses and networks.
+fn parse_hosts(key: &str, value: &str) -> Result<Vec<BanPattern>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(|host| match BanPattern::parse(host) {
+            Some(pattern @ (BanPattern::Address(_) | BanPattern::Network(..))) => Ok(pattern),
+            _ => Err(ConfigError::new(&format!("invalid host `{}` for `{}`", host, key))),
+        })
+        .collect()
+}
+
+fn parse_address(key: &str, value: &str) -> Result<Option<SocketAddr>, ConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse_value(key, value).map(Some)
+}
+
+// an address only reachable from this machine.
+fn parse_loopback(key: &str, value: &str) -> Result<Option<SocketAddr>, ConfigError> {
+    let address = match parse_address(key, value)? {
+        Some(address) => address,
+        None => return Ok(None),
+    };
+    if !address.ip().is_loopback() {
+        return Err(ConfigError::new(&format!(
+            "`{}` must be a loopback address, not {}",
+            key, address
+        )));
+    }
+    Ok(Some(address))
+}
+
+fn parse_url(key: &str, value: &str) -> Result<Option<WebhookUrl>, ConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|error| ConfigError::new(&format!("invalid value for `{}`: {}", key, error)))
+}
+
+// an empty value means the setting is not used.
+fn parse_path(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    details: String,
+}
+
+impl ConfigError {
+    pub fn new(msg: &str) -> ConfigError {
+        ConfigError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl OtherError for ConfigError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_parse_overrides_defaults() {
+        let text = "# comment\n\nrate_messages_per_sec = 1.5\nmute_secs=60\nban_file = bans.txt\n";
+        let config = ServerConfig::parse(text).unwrap();
+        assert_eq!(config.rate_messages_per_sec, 1.5);
+        assert_eq!(config.mute_secs, 60);
+        assert_eq!(config.ban_file, Some(String::from("bans.txt")));
+
+        let config = ServerConfig::parse("operator_hosts = 127.0.0.1, 10.0.0.0/8").unwrap();
+        assert_eq!(config.operator_hosts.len(), 2);
+        assert_eq!(
+            config.rate_message_burst,
+            ServerConfig::default().rate_message_burst
+        );
+    }
+
+    #[test]
+    fn verify_parse_rejects_unknown_settings() {
+        assert!(ServerConfig::parse("colour = blue").is_err());
+        assert!(ServerConfig::parse("mute_secs = soon").is_err());
+        assert!(ServerConfig::parse("mute_secs").is_err());
+        assert!(ServerConfig::parse("operator_hosts = nick:bob").is_err());
+        assert!(ServerConfig::parse("admin_address = 0.0.0.0:1154").is_err());
+        assert!(ServerConfig::parse("admin_address = 127.0.0.1:1154").is_ok());
+        assert!(ServerConfig::parse("log_level = chatty").is_err());
+        assert!(ServerConfig::parse("metrics_address = localhost").is_err());
+        assert!(ServerConfig::parse("metrics_address = 0.0.0.0:9153").is_ok());
+        assert!(ServerConfig::parse("api_address = 10.0.0.1:1155").is_err());
+        assert!(ServerConfig::parse("webhook_url = https://hooks.local/").is_err());
+        assert!(ServerConfig::parse("webhook_url = http://hooks.local/chat").is_ok());
+        assert!(ServerConfig::parse("webhook_queue = -1").is_err());
+        assert!(ServerConfig::parse("webhook_queue = 0").is_err());
+    }
+}
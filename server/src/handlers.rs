@@ -0,0 +1,219 @@
+// custom commands, added to the server from outside the crate.
+//
+// A command is a type implementing `CommandHandler`, registered with
+// `Server::add_command` before the server runs, i.e.:
+//
+//   struct Roll;
+//
+//   impl CommandHandler for Roll {
+//       fn name(&self) -> &str { "ROLL" }
+//       fn usage(&self) -> &str { "ROLL [sides]" }
+//       fn help(&self) -> &str { "roll a dice, and tell everybody" }
+//       fn handle(&self, args: &str, context: &CommandContext) {
+//           let sides: u32 = args.parse().unwrap_or(6);
+//           let nick = context.nick().unwrap_or_default();
+//           context.broadcast(&format!("{} rolls {}", nick, roll(sides)));
+//       }
+//   }
+//
+// Lines starting with the name of a command are handled by it, instead of
+// being sent to the chat. Commands have their help text listed by HELP.
+
+use crate::commands::*;
+use crate::ClientsNameArray;
+use crate::ClientsStreamArray;
+use crate::SharedState;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub trait CommandHandler: Send + Sync {
+    // the command, as typed by the users. It must be a single word, and it
+    // is matched case sensitively, like the built-in commands.
+    fn name(&self) -> &str;
+
+    // how to use the command, shown by HELP, i.e.: `ROLL [sides]`
+    fn usage(&self) -> &str {
+        self.name()
+    }
+
+    // what the command does, in a few words, shown by HELP.
+    fn help(&self) -> &str;
+
+    // only users that joined the chat can use the command.
+    fn requires_join(&self) -> bool {
+        true
+    }
+
+    // only operators can use the command.
+    fn operator_only(&self) -> bool {
+        false
+    }
+
+    // run the command, with the text after the command name.
+    fn handle(&self, args: &str, context: &CommandContext);
+}
+
+// the client running a command, and what a command can do with it.
+pub struct CommandContext<'a> {
+    pub(crate) index: usize,
+    pub(crate) clients_array: &'a ClientsNameArray,
+    pub(crate) clients_streams: &'a ClientsStreamArray,
+    pub(crate) state: &'a SharedState,
+}
+
+impl CommandContext<'_> {
+    // name of the client, if it joined the chat.
+    pub fn nick(&self) -> Option<String> {
+        get_client_name_at_position_i(self.index, self.clients_array)
+    }
+
+    pub fn is_operator(&self) -> bool {
+        self.state.is_operator(self.index)
+    }
+
+    // names of the users in the chat.
+    pub fn users(&self) -> Vec<String> {
+        self.clients_array
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    // send a message to the client running the command.
+    pub fn reply(&self, text: &str) {
+        send_msg_to_ith_client(
+            text.as_bytes(),
+            self.index,
+            self.clients_array,
+            self.clients_streams,
+            self.state,
+        );
+    }
+
+    // send a message to the user with the given name. Returns false if there
+    // is no such user.
+    pub fn send_to(&self, nick: &str, text: &str) -> bool {
+        match find_client_by_name(nick, self.clients_array) {
+            Some(index) => {
+                send_msg_to_ith_client(
+                    text.as_bytes(),
+                    index,
+                    self.clients_array,
+                    self.clients_streams,
+                    self.state,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    // send a message to everybody in the chat, the client included.
+    pub fn broadcast(&self, text: &str) {
+        broadcast_msg_to_other_names(
+            text.as_bytes(),
+            self.index,
+            self.clients_array,
+            self.clients_streams,
+            self.state,
+        );
+        if self.nick().is_some() {
+            self.reply(text);
+        }
+    }
+}
+
+// the custom commands, by name.
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<String, Arc<dyn CommandHandler>>,
+}
+
+impl CommandRegistry {
+    // add a command. Its name cannot be the one of a built-in command, or of
+    // another command already registered.
+    pub fn register(&mut self, handler: Arc<dyn CommandHandler>) -> Result<(), String> {
+        let name = handler.name().to_string();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid command name: {:?}", name));
+        }
+        if BUILTIN_COMMANDS.iter().any(|command| command.name == name) {
+            return Err(format!("{} is a built-in command", name));
+        }
+        if self.commands.contains_key(&name) {
+            return Err(format!("the command {} is already registered", name));
+        }
+        self.commands.insert(name, handler);
+        Ok(())
+    }
+
+    // the command for the given input line, if it is a registered one.
+    pub fn find(&self, input: &str) -> Option<Arc<dyn CommandHandler>> {
+        let name = input.split_whitespace().next()?;
+        self.commands.get(name).cloned()
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Arc<dyn CommandHandler>> {
+        self.commands.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    struct Roll;
+
+    impl CommandHandler for Roll {
+        fn name(&self) -> &str {
+            "ROLL"
+        }
+
+        fn help(&self) -> &str {
+            "roll a dice"
+        }
+
+        fn handle(&self, _args: &str, context: &CommandContext) {
+            context.reply("4");
+        }
+    }
+
+    struct Named(&'static str);
+
+    impl CommandHandler for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn help(&self) -> &str {
+            ""
+        }
+
+        fn handle(&self, _args: &str, _context: &CommandContext) {}
+    }
+
+    #[test]
+    fn verify_register_and_find() {
+        let mut registry = CommandRegistry::default();
+        registry.register(Arc::new(Roll)).unwrap();
+        assert!(registry.register(Arc::new(Roll)).is_err());
+        assert_eq!(registry.find("ROLL 20\n").unwrap().name(), "ROLL");
+        assert!(registry.find("roll").is_none());
+        assert!(registry.find("ROLLING").is_none());
+        assert!(registry.find("").is_none());
+    }
+
+    #[test]
+    fn verify_invalid_names_are_rejected() {
+        let mut registry = CommandRegistry::default();
+        assert!(registry.register(Arc::new(Named("WHO"))).is_err());
+        assert!(registry.register(Arc::new(Named("HELP"))).is_err());
+        assert!(registry.register(Arc::new(Named("TWO WORDS"))).is_err());
+        assert!(registry.register(Arc::new(Named(""))).is_err());
+    }
+}
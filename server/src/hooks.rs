@@ -0,0 +1,71 @@
+// event hooks, to extend the server without changing it.
+//
+// A program embedding the server registers its hooks with `Server::add_hook`
+// to be told what happens in the chat. Every method does nothing by default,
+// so a hook only implements the events it needs, i.e.:
+//
+//   struct JoinCounter(AtomicUsize);
+//
+//   impl EventHook for JoinCounter {
+//       fn on_join(&self, _nick: &str) {
+//           self.0.fetch_add(1, Ordering::Relaxed);
+//       }
+//   }
+//
+// Chat messages and commands can also be changed or stopped by a hook, i.e.
+// to filter words or to keep bots from flooding the chat:
+//
+//   impl EventHook for Filter {
+//       fn on_message(&self, _nick: &str, text: &str) -> Verdict {
+//           if text.contains("spam") {
+//               Verdict::Veto(String::from("no spam, please"))
+//           } else {
+//               Verdict::Replace(text.replace("darn", "****"))
+//           }
+//       }
+//   }
+//
+// Hooks run in the order they were added, each one seeing the text as left
+// by the previous ones. The first veto stops the message, and the next hooks
+// are not called.
+//
+// Hooks are called from the client threads, without holding any lock of the
+// server, so they must be thread safe and should return quickly. They run
+// before the other users are told about the event.
+
+use std::net::SocketAddr;
+
+// what to do with a message or a command, as decided by a hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    // let it through, unchanged.
+    Continue,
+    // let it through, with the given text instead.
+    Replace(String),
+    // stop it. The reason is sent back to the user, unless it is empty: then
+    // only a SAY is answered, as it always is.
+    Veto(String),
+}
+
+pub trait EventHook: Send + Sync {
+    // a client connected, and was accepted.
+    fn on_connect(&self, _address: SocketAddr) {}
+
+    // a client joined the chat with the given name.
+    fn on_join(&self, _nick: &str) {}
+
+    // a user left the chat, or was disconnected.
+    fn on_leave(&self, _nick: &str) {}
+
+    // a user sent a chat message to the others. The text has no line break.
+    fn on_message(&self, _nick: &str, _text: &str) -> Verdict {
+        Verdict::Continue
+    }
+
+    // a client sent a command, built-in or custom, i.e.: `WHO a*`. The nick
+    // is None if the client did not join the chat yet. A replaced command
+    // line is run instead of the original one.
+    fn on_command(&self, _nick: Option<&str>, _line: &str) -> Verdict {
+        Verdict::Continue
+    }
+}
@@ -0,0 +1,463 @@
+// chat server library.
+//
+// The server can be embedded in other programs, or started from tests, i.e.:
+//
+//   let mut server = Server::bind("127.0.0.1:0", ServerConfig::default())?;
+//   server.add_hook(Arc::new(MyHook));      // optional, see `EventHook`
+//   let handle = server.handle();
+//   thread::spawn(move || server.run());
+//   ...
+//   println!("{:?}", handle.stats());
+//   handle.shutdown();
+//
+// The `server` binary (src/main.rs) is a thin wrapper over this library.
+
+// idioms of the first version of the server, kept as they were written
+#![allow(
+    clippy::needless_borrow,
+    clippy::redundant_slicing,
+    clippy::bool_assert_comparison,
+    clippy::assertions_on_constants
+)]
+
+use std::io::{self, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub mod admin;
+pub mod api;
+pub mod aux;
+pub mod bans;
+pub mod commands;
+pub mod config;
+pub mod handlers;
+pub mod hooks;
+pub mod logger;
+pub mod metrics;
+pub mod motd;
+pub mod names;
+pub mod ratelimit;
+pub mod state;
+pub mod webhook;
+pub mod websocket;
+use crate::admin::*;
+use crate::bans::BanList;
+use crate::commands::*;
+pub use crate::config::ServerConfig;
+pub use crate::handlers::{CommandContext, CommandHandler};
+pub use crate::hooks::{EventHook, Verdict};
+use crate::state::{ClientInfo, ServerState, ServerStats, StatsSnapshot};
+
+pub const MAX_CLIENTS: usize = 20; // max clients cannot be >32, because the way the array initialization is done
+pub const MAX_NAME_LEN: usize = 20; // in characters as shown (grapheme clusters)
+pub const MAX_MESSAGE_SIZE: usize = 512;
+pub const MAX_HOSTNAME_SIZE: usize = 50;
+// the only room of the chat
+pub const ROOM: &str = "main";
+// time to tell a client that the server is shutting down, before closing it anyway
+const SHUTDOWN_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+pub const VERSION: &[u8] = b"Simple Rust Chat Server v0.1\n";
+// https://www.sitepoint.com/rust-global-variables/
+// https://www.howtosolutions.net/2022/12/rust-create-global-variable-mutable-struct-without-unsafe-code-block/
+
+// TODO: create a structure for this
+// TODO: preferibly, this may be static
+pub type ClientsStreamArray = Arc<Mutex<[Option<TcpStream>; MAX_CLIENTS]>>;
+pub type ClientsNameArray = Arc<Mutex<[Option<String>; MAX_CLIENTS]>>;
+pub type SharedState = Arc<ServerState>;
+
+// a chat server bound to its listening socket, ready to `run`.
+pub struct Server {
+    listener: TcpListener,
+    clients_names: ClientsNameArray,
+    clients_streams: ClientsStreamArray,
+    state: SharedState,
+}
+
+impl Server {
+    // bind the server to an address. Port 0 picks a free port, see
+    // `local_addr`. The ban list, the admin socket and the metrics endpoint
+    // of the configuration are set up here too.
+    pub fn bind(address: impl ToSocketAddrs, config: ServerConfig) -> io::Result<Server> {
+        let bans = match &config.ban_file {
+            Some(path) => BanList::load(path).map_err(io::Error::other)?,
+            None => BanList::default(),
+        };
+        let admin_address = config.admin_address;
+        let metrics_address = config.metrics_address;
+        let websocket_address = config.websocket_address;
+        let api = match (config.api_address, &config.api_token) {
+            (Some(address), Some(token)) => Some((address, token.clone())),
+            (Some(_), None) => return Err(io::Error::other("`api_address` needs an `api_token`")),
+            (None, _) => None,
+        };
+        let state: SharedState = Arc::new(ServerState::new(config, None, bans));
+
+        // initialize array of clients
+        // ref: https://www.joshmcguigan.com/blog/array-initialization-rust/
+        // The primary downside to this method is it only works for arrays up to size 32.
+        const { assert!(MAX_CLIENTS < 32) };
+
+        // TODO: create a structure for this
+        let clients_streams: ClientsStreamArray = Arc::new(Mutex::new(Default::default()));
+        let clients_names: ClientsNameArray = Arc::new(Mutex::new(Default::default()));
+
+        if let Some(address) = admin_address {
+            spawn_admin_socket(address, &clients_names, &clients_streams, &state)?;
+        }
+        if let Some(address) = metrics_address {
+            metrics::spawn_metrics_endpoint(address, &clients_names, &clients_streams, &state)?;
+        }
+        if let Some(address) = websocket_address {
+            websocket::spawn_websocket_gateway(address, &clients_names, &clients_streams, &state)?;
+        }
+        if let Some((address, token)) = api {
+            api::spawn_api_endpoint(address, token, &clients_names, &clients_streams, &state)?;
+        }
+
+        // create a listening socket
+        let listener = TcpListener::bind(address)?;
+        Ok(Server {
+            listener,
+            clients_names,
+            clients_streams,
+            state,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // register a hook to be told about the events of the chat.
+    pub fn add_hook(&mut self, hook: Arc<dyn EventHook>) {
+        self.state.hooks.write().unwrap().push(hook);
+    }
+
+    // register a custom command (see `CommandHandler`). It fails if there
+    // is already a command with the same name.
+    pub fn add_command(&mut self, handler: Arc<dyn CommandHandler>) -> Result<(), String> {
+        self.state.commands.write().unwrap().register(handler)
+    }
+
+    // handle to control the server once it runs, from another thread.
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            address: self.listener.local_addr().ok(),
+            clients_names: Arc::clone(&self.clients_names),
+            clients_streams: Arc::clone(&self.clients_streams),
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    // file the configuration is read again from, with the RELOAD admin
+    // command.
+    pub fn set_config_path(&self, path: &str) {
+        *self.state.config_path.lock().unwrap() = Some(path.to_string());
+    }
+
+    // accept admin commands typed in the console (stdin).
+    pub fn spawn_admin_console(&self) {
+        spawn_admin_console(&self.clients_names, &self.clients_streams, &self.state);
+    }
+
+    // accept clients, each one handled in its own thread. It returns when
+    // the server is shut down (see `ServerHandle::shutdown`), or if
+    // accepting connections fails.
+    pub fn run(self) -> io::Result<()> {
+        let clients_streams = &self.clients_streams;
+        let clients_names = &self.clients_names;
+        let state = &self.state;
+
+        loop {
+            // you could do the same without a loop with `listener.incomming()`.
+            match self.listener.accept() {
+                // new connection accepted
+                Ok((stream, addr)) => {
+                    if state.is_shutting_down() {
+                        logger::info("shutdown", &[]);
+                        return Ok(());
+                    }
+                    admit_client(stream, addr, clients_names, clients_streams, state);
+                }
+                Err(error) => {
+                    logger::error("accept_failed", &[("error", &error)]);
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+// check a new connection against the bans and limits, and if it is allowed
+// handle it in its own thread. Used for the chat connections, and for the
+// ones from other gateways (see `websocket`), with their remote address.
+pub(crate) fn admit_client(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    clients_names: &ClientsNameArray,
+    clients_streams: &ClientsStreamArray,
+    state: &SharedState,
+) {
+    let config = state.config().clone();
+
+    if let Some(ban) = state
+        .bans
+        .lock()
+        .unwrap()
+        .find_address(addr.ip(), SystemTime::now())
+    {
+        logger::warn(
+            "reject",
+            &[("peer", &addr), ("reason", &"banned"), ("ban", ban)],
+        );
+        let msg = format!(">>> you are banned from this server ({})\n", ban);
+        let _ = stream.write_all(msg.as_bytes());
+        ServerStats::increment(&state.stats.connections_rejected);
+        return;
+    }
+
+    // the position found is taken before another connection looks for one
+    let admission = state.admission.lock().unwrap();
+
+    if config.max_connections_per_ip > 0
+        && count_connections_from(addr.ip(), clients_streams, state)
+            >= config.max_connections_per_ip
+    {
+        logger::warn(
+            "reject",
+            &[("peer", &addr), ("reason", &"too many connections")],
+        );
+        let _ = stream.write_all(b">>> too many connections from your address\n");
+        ServerStats::increment(&state.stats.connections_rejected);
+        return;
+    }
+
+    for i in 0..MAX_CLIENTS {
+        // check for an empty spot on the clients array
+        //
+        // TODO: try to remove all unwrap(), and use instead
+        // expect(), or unwrap_or(), ? operator, if let ...
+        // or better error handling
+        if clients_streams.lock().unwrap()[i].is_none() {
+            ServerStats::increment(&state.stats.connections_accepted);
+            let id = ServerStats::get(&state.stats.connections_accepted);
+            logger::info("connect", &[("conn", &id), ("peer", &addr), ("pos", &i)]);
+
+            let operator = config
+                .operator_hosts
+                .iter()
+                .any(|host| host.matches_address(addr.ip()));
+            state.clients_info.lock().unwrap()[i] = Some(ClientInfo::new(id, addr, operator));
+
+            {
+                // include/update this stream, in the array of clientsStreams
+                clients_streams.lock().unwrap()[i] = Some(
+                    stream
+                        .try_clone()
+                        .expect("failure trying to clone a stream"),
+                );
+            }
+            drop(admission);
+            state.run_hooks(|hook| hook.on_connect(addr));
+
+            // a shutdown that started meanwhile did not see this stream, so
+            // close it here, and its thread cleans up as usual
+            if state.is_shutting_down() {
+                let _ = stream.write_all(b">>> the server is shutting down\n");
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+
+            let client_names_array = Arc::clone(clients_names);
+            let client_stream_array = Arc::clone(clients_streams);
+            let client_state = Arc::clone(state);
+
+            thread::spawn(move || {
+                // connection suceeded
+                handle_client(
+                    stream,
+                    i,
+                    &client_names_array,
+                    &client_stream_array,
+                    &client_state,
+                )
+                .unwrap_or_else(|error| logger::debug("client_thread_end", &[("nick", &error)]));
+            });
+
+            return; //once the new connection is registered, we are done.
+        }
+    }
+
+    logger::warn("reject", &[("peer", &addr), ("reason", &"server full")]);
+    let _ = stream.write_all(b">>> the server is full, try again later\n");
+    ServerStats::increment(&state.stats.connections_rejected);
+}
+
+// handle to a running server, that can be cloned and sent to other threads.
+#[derive(Clone)]
+pub struct ServerHandle {
+    address: Option<SocketAddr>,
+    clients_names: ClientsNameArray,
+    clients_streams: ClientsStreamArray,
+    state: SharedState,
+}
+
+impl ServerHandle {
+    // names of the users in the chat.
+    pub fn users(&self) -> Vec<String> {
+        self.clients_names
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        let stats = &self.state.stats;
+        StatsSnapshot {
+            uptime: self.state.started_at.elapsed(),
+            connected_clients: self
+                .clients_streams
+                .lock()
+                .unwrap()
+                .iter()
+                .flatten()
+                .count(),
+            joined_users: self.users().len(),
+            connections_accepted: ServerStats::get(&stats.connections_accepted),
+            connections_rejected: ServerStats::get(&stats.connections_rejected),
+            messages_received: ServerStats::get(&stats.messages_received),
+            messages_broadcast: ServerStats::get(&stats.messages_broadcast),
+            bytes_received: ServerStats::get(&stats.bytes_received),
+            bytes_sent: ServerStats::get(&stats.bytes_sent),
+            commands: stats.commands.lock().unwrap().clone(),
+        }
+    }
+
+    // stop accepting clients, and disconnect the ones connected. `run`
+    // returns once the listening socket is closed. The admin socket and the
+    // metrics endpoint, if any, keep running until the process ends.
+    pub fn shutdown(&self) {
+        if self.state.shutting_down.swap(true, Ordering::Relaxed) {
+            return; // already done
+        }
+
+        // `accept` only returns with a new connection, so make one
+        if let Some(mut address) = self.address {
+            if address.ip().is_unspecified() {
+                address.set_ip(match address.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                });
+            }
+            let _ = TcpStream::connect(address);
+        }
+
+        // the client threads see their connections closing, and finish. The
+        // streams are copied first, so a slow client does not hold the lock
+        let streams: Vec<TcpStream> = self
+            .clients_streams
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .filter_map(|stream| stream.try_clone().ok())
+            .collect();
+        for mut stream in streams {
+            let _ = stream.set_write_timeout(Some(SHUTDOWN_WRITE_TIMEOUT));
+            let _ = stream.write_all(b">>> the server is shutting down\n");
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*; //to include everything above in the file to test
+    use crate::aux::*;
+
+    #[test]
+    fn verify_command() {
+        assert!(check_command("Hello", "Hello World!"));
+    }
+
+    //#[test]
+    //fn verify_join() {
+    //    assert!(check_join("JOIN Alice"));
+    //}
+
+    #[test]
+    fn verify_join_u8() {
+        assert!(check_join_u8(String::from("  JOIN Alice").as_bytes()));
+    }
+
+    #[test]
+    fn verify_check_who() {
+        assert_eq!(check_who("WHO"), true);
+    }
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
+    #[test]
+    fn verify_max_clients() {
+        // given how the clients array it is initialized,
+        // its size cannot be larger than 32
+        assert!(MAX_CLIENTS < 32);
+    }
+
+    #[test]
+    fn verify_first_word() {
+        let my_string = String::from("Hello World!");
+        let word = first_word(&my_string);
+        assert_eq!("Hello", word);
+    }
+
+    #[test]
+    fn verify_first_word_with_initial_space() {
+        let my_string = String::from("    Hello World!");
+        let word = first_word(&my_string);
+        assert_eq!("Hello", word);
+    }
+
+    #[test]
+    fn verify_first_word_with_initial_tab() {
+        let my_string = String::from("\tHello World!");
+        let word = first_word(&my_string);
+        assert_eq!("Hello", word);
+    }
+
+    #[test]
+    fn verify_first_2_words_with_initial_space() {
+        let my_string = String::from("    Hello    World!");
+        let (word1, word2) = first_2_words(&my_string);
+        assert_eq!(Some("Hello"), word1);
+        assert_eq!(Some("World!"), word2);
+    }
+
+    #[test]
+    fn verify_first_2_words_with_initial_space_2() {
+        let my_string = String::from("\tHello\tWorld!");
+        let (word1, word2) = first_2_words(&my_string);
+        assert_eq!(Some("Hello"), word1);
+        assert_eq!(Some("World!"), word2);
+    }
+
+    #[test]
+    fn verify_first_2_words_with_initial_space_3() {
+        let my_string = String::from(" \t Hello \t World!");
+        let (word1, word2) = first_2_words(&my_string);
+        assert_eq!(Some("Hello"), word1);
+        assert_eq!(Some("World!"), word2);
+    }
+}
@@ -0,0 +1,248 @@
+// leveled, structured logging for the server.
+//
+// every log entry is one line, with a timestamp, a level, the event type and
+// a list of `key=value` fields, i.e.:
+//
+//   2024-05-01T10:20:30Z INFO event=join conn=3 peer=127.0.0.1:50602 nick=alice
+//
+// values with spaces or quotes are quoted. Entries go to stderr, or appended
+// to the `log_file` of the configuration. The content of chat messages is
+// only logged when `log_chat` is enabled.
+//
+// The logger is global for the whole process, like stdout. It starts with
+// the default settings, and `configure` applies the ones in the config.
+
+use crate::config::ServerConfig;
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Level, ()> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(()),
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    log_chat: bool,
+    file: Option<File>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    level: Level::Info,
+    log_chat: false,
+    file: None,
+});
+
+// apply the logging settings of the configuration.
+pub fn configure(config: &ServerConfig) -> Result<(), String> {
+    let file = match &config.log_file {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|error| format!("cannot open {}: {}", path, error))?,
+        ),
+        None => None,
+    };
+
+    let mut logger = LOGGER.lock().unwrap();
+    logger.level = config.log_level;
+    logger.log_chat = config.log_chat;
+    logger.file = file;
+    Ok(())
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.lock().unwrap().level
+}
+
+// check if the content of the chat messages can be logged.
+pub fn chat_enabled() -> bool {
+    LOGGER.lock().unwrap().log_chat
+}
+
+// write a log entry, if its level is enabled.
+pub fn log(level: Level, event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    let mut logger = LOGGER.lock().unwrap();
+    if level > logger.level {
+        return;
+    }
+
+    let line = format_entry(SystemTime::now(), level, event, fields);
+    let written = match logger.file.as_mut() {
+        Some(file) => file.write_all(line.as_bytes()),
+        None => io::stderr().write_all(line.as_bytes()),
+    };
+    if let Err(error) = written {
+        eprintln!("cannot write the log: {}", error);
+    }
+}
+
+pub fn error(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Error, event, fields)
+}
+
+pub fn warn(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Warn, event, fields)
+}
+
+pub fn info(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Info, event, fields)
+}
+
+pub fn debug(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Debug, event, fields)
+}
+
+pub fn trace(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Trace, event, fields)
+}
+
+fn format_entry(
+    time: SystemTime,
+    level: Level,
+    event: &str,
+    fields: &[(&str, &dyn fmt::Display)],
+) -> String {
+    let mut line = format!("{} {} event={}", format_timestamp(time), level, event);
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, quote(&value.to_string())));
+    }
+    line.push('\n');
+    line
+}
+
+// quote a value if needed, so every entry stays in a single line.
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '=' || c.is_control());
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// format a time as UTC in RFC 3339, i.e.: 2024-05-01T10:20:30Z
+// reference: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn verify_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn verify_format_entry() {
+        let line = format_entry(
+            UNIX_EPOCH,
+            Level::Info,
+            "message",
+            &[
+                ("conn", &3),
+                ("nick", &"alice"),
+                ("text", &"hello \"world\"\n"),
+            ],
+        );
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00Z INFO event=message conn=3 nick=alice text=\"hello \\\"world\\\"\\n\"\n"
+        );
+    }
+
+    #[test]
+    fn verify_level_order() {
+        assert!(Level::Error < Level::Warn);
+        assert!(Level::Debug < Level::Trace);
+        assert_eq!("debug".parse::<Level>(), Ok(Level::Debug));
+        assert!("loud".parse::<Level>().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// metrics endpoint, in the Prometheus text format.
+//
+// When `metrics_address` is configured, the server answers HTTP requests to
+// `GET /metrics` on that address with the current gauges and counters, i.e.:
+//
+//   # HELP chat_connected_clients Open client connections.
+//   # TYPE chat_connected_clients gauge
+//   chat_connected_clients 3
+//
+// reference: https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use crate::logger;
+use crate::state::ServerStats;
+use crate::ClientsNameArray;
+use crate::ClientsStreamArray;
+use crate::SharedState;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// render all the metrics of the server.
+pub fn render_metrics(
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> String {
+    let connected = stream_array.lock().unwrap().iter().flatten().count();
+    let joined = clients_array.lock().unwrap().iter().flatten().count();
+    let stats = &state.stats;
+
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+        text.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        ));
+    };
+
+    metric(
+        "chat_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        state.started_at.elapsed().as_secs(),
+    );
+    metric(
+        "chat_connected_clients",
+        "gauge",
+        "Open client connections.",
+        connected as u64,
+    );
+    metric(
+        "chat_joined_users",
+        "gauge",
+        "Clients that joined the chat.",
+        joined as u64,
+    );
+    metric(
+        "chat_connections_accepted_total",
+        "counter",
+        "Client connections accepted.",
+        ServerStats::get(&stats.connections_accepted),
+    );
+    metric(
+        "chat_connections_rejected_total",
+        "counter",
+        "Client connections rejected by bans or limits.",
+        ServerStats::get(&stats.connections_rejected),
+    );
+    metric(
+        "chat_messages_received_total",
+        "counter",
+        "Lines received from the clients, commands included.",
+        ServerStats::get(&stats.messages_received),
+    );
+    metric(
+        "chat_messages_broadcast_total",
+        "counter",
+        "Chat messages broadcast to the other users.",
+        ServerStats::get(&stats.messages_broadcast),
+    );
+    metric(
+        "chat_bytes_received_total",
+        "counter",
+        "Bytes received from the clients.",
+        ServerStats::get(&stats.bytes_received),
+    );
+    metric(
+        "chat_bytes_sent_total",
+        "counter",
+        "Bytes sent to the clients.",
+        ServerStats::get(&stats.bytes_sent),
+    );
+
+    text.push_str("# HELP chat_commands_total Commands received, by command.\n");
+    text.push_str("# TYPE chat_commands_total counter\n");
+    for (command, count) in stats.commands.lock().unwrap().iter() {
+        text.push_str(&format!(
+            "chat_commands_total{{command=\"{}\"}} {}\n",
+            command, count
+        ));
+    }
+
+    text
+}
+
+// serve the metrics over HTTP in a separate thread.
+pub fn spawn_metrics_endpoint(
+    address: SocketAddr,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    logger::info("metrics_listen", &[("address", &address)]);
+
+    let clients_array = Arc::clone(clients_array);
+    let stream_array = Arc::clone(stream_array);
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        // requests are small and quick to answer, so they are served one at a time
+        for stream in listener.incoming().flatten() {
+            if let Err(error) = serve_request(stream, &clients_array, &stream_array, &state) {
+                logger::debug("metrics_request_failed", &[("error", &error)]);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve_request(
+    stream: TcpStream,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, until the empty line
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            ("200 OK", render_metrics(clients_array, stream_array, state))
+        }
+        (Some("GET"), Some(_)) => ("404 Not Found", String::from("not found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("method not allowed\n"),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let mut stream = stream;
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bans::BanList;
+    use crate::config::ServerConfig;
+    use crate::state::ServerState;
+    use std::sync::Mutex;
+
+    #[test]
+    fn verify_render_metrics() {
+        let names: ClientsNameArray = Arc::new(Mutex::new(Default::default()));
+        let streams: ClientsStreamArray = Arc::new(Mutex::new(Default::default()));
+        let state: SharedState = Arc::new(ServerState::new(
+            ServerConfig::default(),
+            None,
+            BanList::default(),
+        ));
+        ServerStats::add(&state.stats.bytes_received, 42);
+        state.stats.count_command("WHO");
+        state.stats.count_command("WHO");
+
+        let text = render_metrics(&names, &streams, &state);
+        assert!(text.contains("# TYPE chat_connected_clients gauge\nchat_connected_clients 0\n"));
+        assert!(text.contains("\nchat_bytes_received_total 42\n"));
+        assert!(text.contains("\nchat_commands_total{command=\"WHO\"} 2\n"));
+    }
+}
@@ -0,0 +1,99 @@
+// message of the day.
+//
+// The MOTD is read from the `motd_file` of the configuration, and sent to
+// every client when it connects, and when it uses the MOTD command. The file
+// is read again whenever its modification time changes, so it can be edited
+// while the server is running.
+
+use crate::logger;
+
+use std::fs;
+use std::time::SystemTime;
+
+#[derive(Debug, Default)]
+pub struct Motd {
+    path: Option<String>,
+    modified: Option<SystemTime>, // modification time of the file last read
+    lines: Vec<String>,
+}
+
+impl Motd {
+    pub fn new(path: Option<String>) -> Motd {
+        Motd {
+            path,
+            modified: None,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    // current lines of the MOTD, reading the file again if it changed. A
+    // missing or unreadable file is an empty MOTD.
+    pub fn lines(&mut self) -> &[String] {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return &[],
+        };
+
+        match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) if Some(modified) == self.modified => {}
+            Ok(modified) => match fs::read_to_string(path) {
+                Ok(text) => {
+                    logger::debug("motd_load", &[("file", path)]);
+                    self.modified = Some(modified);
+                    self.lines = text.trim_end().lines().map(str::to_string).collect();
+                }
+                Err(error) => self.unreadable(&error),
+            },
+            Err(error) => self.unreadable(&error),
+        }
+        &self.lines
+    }
+
+    fn unreadable(&mut self, error: &std::io::Error) {
+        if self.modified.is_some() || !self.lines.is_empty() {
+            logger::warn(
+                "motd_unreadable",
+                &[("file", &self.path().unwrap_or("")), ("error", error)],
+            );
+        }
+        self.modified = None;
+        self.lines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn verify_motd_without_file() {
+        assert!(Motd::default().lines().is_empty());
+        let mut motd = Motd::new(Some(String::from("/nonexistent/motd.txt")));
+        assert!(motd.lines().is_empty());
+    }
+
+    #[test]
+    fn verify_motd_reloads_when_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("motd-{}.txt", std::process::id()));
+        fs::write(&path, "welcome\nbe nice\n\n").unwrap();
+
+        let mut motd = Motd::new(Some(path.to_str().unwrap().to_string()));
+        assert_eq!(motd.lines(), ["welcome", "be nice"]);
+
+        fs::write(&path, "new rules").unwrap();
+        // make sure the modification time changes, even on coarse filesystems
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(motd.lines(), ["new rules"]);
+
+        fs::remove_file(&path).unwrap();
+        assert!(motd.lines().is_empty());
+    }
+}
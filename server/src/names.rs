@@ -0,0 +1,95 @@
+// nicknames of the chat users.
+//
+// A name is any UTF-8 text without spaces or control characters, of up to
+// MAX_NAME_LEN characters as the user sees them: grapheme clusters, so an 'é'
+// written as 'e' plus a combining accent counts as one. Names are stored in
+// NFC, and compared in a case insensitive NFKC form, so two users cannot take
+// names that look the same.
+//
+// reference: https://unicode.org/reports/tr15/ (normalization forms)
+// reference: https://unicode.org/reports/tr29/ (grapheme clusters)
+
+use crate::aux::glob_match;
+use crate::MAX_NAME_LEN;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+// check a name given with JOIN, returning it normalized.
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name: String = name.nfc().collect();
+    if name.is_empty() {
+        return Err(String::from("the name cannot be empty"));
+    }
+    if name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(String::from(
+            "the name cannot have spaces or control characters",
+        ));
+    }
+    if name.graphemes(true).count() > MAX_NAME_LEN {
+        return Err(format!(
+            "the name cannot be longer than {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    Ok(name)
+}
+
+// form of a name used to compare it with others.
+pub fn name_key(name: &str) -> String {
+    name.nfkc().collect::<String>().to_lowercase()
+}
+
+pub fn same_name(name: &str, other: &str) -> bool {
+    name_key(name) == name_key(other)
+}
+
+// match a name against a pattern of `glob_match`, i.e.: `troll*`, in the
+// form names are compared in.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    glob_match(&name_key(pattern), &name_key(name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_validate_name() {
+        assert_eq!(validate_name("alice"), Ok(String::from("alice")));
+        assert_eq!(validate_name("Zoë"), Ok(String::from("Zoë")));
+        assert!(validate_name("").is_err());
+        assert!(validate_name("bad\u{7}name").is_err());
+        assert!(validate_name("no\u{a0}space").is_err());
+    }
+
+    #[test]
+    fn verify_name_length_in_graphemes() {
+        // 20 'é' written with combining accents: 40 chars, 60 bytes
+        let long_accents = "e\u{301}".repeat(MAX_NAME_LEN);
+        assert_eq!(
+            validate_name(&long_accents).unwrap(),
+            "\u{e9}".repeat(MAX_NAME_LEN)
+        );
+        // family emoji, made of several code points joined with ZWJ
+        let families = "👨‍👩‍👧".repeat(MAX_NAME_LEN);
+        assert!(validate_name(&families).is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn verify_same_name() {
+        assert!(same_name("Alice", "alice"));
+        assert!(same_name("Zo\u{eb}", "Zoe\u{308}"));
+        assert!(same_name("ｂｏｂ", "bob")); // fullwidth letters
+        assert!(!same_name("alice", "alicia"));
+    }
+
+    #[test]
+    fn verify_name_matches() {
+        assert!(name_matches("bob", "ｂｏｂ"));
+        assert!(name_matches("ｔroll*", "TrollFace"));
+        assert!(!name_matches("bob", "bobby"));
+    }
+}
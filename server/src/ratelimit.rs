@@ -0,0 +1,200 @@
+// flood protection for the chat clients.
+//
+// every connection gets a FloodGuard with two token buckets: one counting
+// messages and one counting bytes. When a message doesn't fit in the buckets
+// it is dropped and the client is warned; too many warnings mute the client
+// for a while, and too many mutes get it disconnected.
+//
+// reference: https://en.wikipedia.org/wiki/Token_bucket
+
+use crate::config::ServerConfig;
+
+use std::time::{Duration, Instant};
+
+// a bucket holding up to `capacity` tokens, refilled at `rate` tokens per
+// second. A rate of 0 means there is no limit.
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.rate <= 0.0
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    // check if `amount` tokens are available. An amount bigger than the
+    // capacity is treated as a full bucket, so it can pass when idle.
+    pub fn has(&mut self, amount: f64, now: Instant) -> bool {
+        if self.is_unlimited() {
+            return true;
+        }
+        self.refill(now);
+        self.tokens >= amount.min(self.capacity)
+    }
+
+    pub fn take(&mut self, amount: f64) {
+        if !self.is_unlimited() {
+            self.tokens = (self.tokens - amount.min(self.capacity)).max(0.0);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FloodVerdict {
+    Allow,          // the message can be processed
+    Warn,           // the message is dropped, and the client warned
+    Mute(Duration), // the message is dropped, and the client muted
+    Disconnect,     // the client keeps flooding, so it is disconnected
+}
+
+pub struct FloodGuard {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    warnings: u32,
+    mutes: u32,
+    last_violation: Option<Instant>,
+    muted_until: Option<Instant>,
+    warnings_before_mute: u32,
+    mute_duration: Duration,
+    mutes_before_disconnect: u32,
+}
+
+impl FloodGuard {
+    pub fn new(config: &ServerConfig, now: Instant) -> FloodGuard {
+        FloodGuard {
+            messages: TokenBucket::new(
+                config.rate_messages_per_sec,
+                config.rate_message_burst,
+                now,
+            ),
+            bytes: TokenBucket::new(config.rate_bytes_per_sec, config.rate_byte_burst, now),
+            warnings: 0,
+            mutes: 0,
+            last_violation: None,
+            muted_until: None,
+            warnings_before_mute: config.warnings_before_mute,
+            mute_duration: Duration::from_secs(config.mute_secs),
+            mutes_before_disconnect: config.mutes_before_disconnect,
+        }
+    }
+
+    // account for a message of `size` bytes, and decide what to do with it.
+    pub fn check(&mut self, size: usize, now: Instant) -> FloodVerdict {
+        let size = size as f64;
+        if self.messages.has(1.0, now) && self.bytes.has(size, now) {
+            self.messages.take(1.0);
+            self.bytes.take(size);
+            return FloodVerdict::Allow;
+        }
+
+        // warnings are forgotten once the client behaves for a while
+        if let Some(last) = self.last_violation {
+            if now.saturating_duration_since(last) > self.mute_duration {
+                self.warnings = 0;
+            }
+        }
+        self.last_violation = Some(now);
+
+        self.warnings += 1;
+        if self.warnings <= self.warnings_before_mute {
+            return FloodVerdict::Warn;
+        }
+
+        self.warnings = 0;
+        if self.mutes >= self.mutes_before_disconnect {
+            return FloodVerdict::Disconnect;
+        }
+        self.mutes += 1;
+        self.muted_until = Some(now + self.mute_duration);
+        FloodVerdict::Mute(self.mute_duration)
+    }
+
+    // time left until the client is allowed to talk again, if it is muted.
+    pub fn muted_for(&self, now: Instant) -> Option<Duration> {
+        match self.muted_until {
+            Some(until) if until > now => Some(until - now),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn test_config() -> ServerConfig {
+        ServerConfig {
+            rate_messages_per_sec: 1.0,
+            rate_message_burst: 2.0,
+            rate_bytes_per_sec: 100.0,
+            rate_byte_burst: 100.0,
+            warnings_before_mute: 1,
+            mutes_before_disconnect: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verify_token_bucket_refills() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1.0, 2.0, now);
+        assert!(bucket.has(2.0, now));
+        bucket.take(2.0);
+        assert!(!bucket.has(1.0, now));
+        assert!(bucket.has(1.0, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn verify_unlimited_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0.0, 0.0, now);
+        bucket.take(1000.0);
+        assert!(bucket.has(1000.0, now));
+    }
+
+    #[test]
+    fn verify_flood_escalation() {
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(&test_config(), now);
+        assert_eq!(guard.check(10, now), FloodVerdict::Allow);
+        assert_eq!(guard.check(10, now), FloodVerdict::Allow);
+        assert_eq!(guard.check(10, now), FloodVerdict::Warn);
+        assert_eq!(
+            guard.check(10, now),
+            FloodVerdict::Mute(Duration::from_secs(30))
+        );
+        assert!(guard.muted_for(now).is_some());
+        assert!(guard.muted_for(now + Duration::from_secs(31)).is_none());
+        assert_eq!(guard.check(10, now), FloodVerdict::Warn);
+        assert_eq!(guard.check(10, now), FloodVerdict::Disconnect);
+    }
+
+    #[test]
+    fn verify_byte_limit() {
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(&test_config(), now);
+        assert_eq!(guard.check(80, now), FloodVerdict::Allow);
+        assert_eq!(guard.check(80, now), FloodVerdict::Warn);
+    }
+}
@@ -0,0 +1,289 @@
+// state shared by all the client threads, besides the arrays of names and
+// streams.
+
+use crate::bans::BanList;
+use crate::config::ServerConfig;
+use crate::handlers::CommandRegistry;
+use crate::hooks::{EventHook, Verdict};
+use crate::logger;
+use crate::motd::Motd;
+use crate::webhook::Webhook;
+use crate::MAX_CLIENTS;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
+
+pub struct ServerState {
+    // the configuration can be replaced at runtime (see `reload_config`)
+    pub config: RwLock<ServerConfig>,
+    pub config_path: Mutex<Option<String>>, // file to read on `reload_config`
+    pub bans: Mutex<BanList>,
+    pub motd: Mutex<Motd>,
+    // the ith element has the data of the client at the ith position of the
+    // names and streams arrays. It is overwritten when a new client takes the
+    // position, so it is only meaningful while the stream is Some.
+    pub clients_info: Mutex<[Option<ClientInfo>; MAX_CLIENTS]>,
+    // held while a new connection is checked against the limits and takes a
+    // position, as the gateways admit clients from their own threads.
+    pub admission: Mutex<()>,
+    pub started_at: Instant,
+    pub stats: ServerStats,
+    pub hooks: RwLock<Vec<Arc<dyn EventHook>>>,
+    pub commands: RwLock<CommandRegistry>, // custom commands
+    pub shutting_down: AtomicBool,
+    pub webhook: Option<Webhook>, // outgoing webhook, if configured
+}
+
+impl ServerState {
+    pub fn new(config: ServerConfig, config_path: Option<String>, bans: BanList) -> ServerState {
+        let motd = Motd::new(config.motd_file.clone());
+        let webhook = config.webhook_url.clone().map(|url| {
+            Webhook::spawn(
+                url,
+                config.webhook_pattern.clone(),
+                config.webhook_queue,
+                config.webhook_retries,
+            )
+        });
+        ServerState {
+            config: RwLock::new(config),
+            config_path: Mutex::new(config_path),
+            bans: Mutex::new(bans),
+            motd: Mutex::new(motd),
+            clients_info: Mutex::new(Default::default()),
+            admission: Mutex::new(()),
+            started_at: Instant::now(),
+            stats: Default::default(),
+            hooks: RwLock::new(Vec::new()),
+            commands: RwLock::new(CommandRegistry::default()),
+            shutting_down: AtomicBool::new(false),
+            webhook,
+        }
+    }
+
+    pub fn config(&self) -> RwLockReadGuard<'_, ServerConfig> {
+        self.config.read().unwrap()
+    }
+
+    // read the configuration file again. The ban list is reloaded too if the
+    // ban file changed, and the MOTD if its file changed. Connections already open keep their rate limits.
+    pub fn reload_config(&self) -> Result<(), String> {
+        let path = match self.config_path.lock().unwrap().clone() {
+            Some(path) => path,
+            None => return Err(String::from("the server was started without a config file")),
+        };
+        let config = ServerConfig::from_file(&path).map_err(|error| error.to_string())?;
+
+        if config.ban_file != self.config().ban_file {
+            let bans = match &config.ban_file {
+                Some(ban_file) => BanList::load(ban_file)?,
+                None => BanList::default(),
+            };
+            *self.bans.lock().unwrap() = bans;
+        }
+
+        if config.motd_file.as_deref() != self.motd.lock().unwrap().path() {
+            *self.motd.lock().unwrap() = Motd::new(config.motd_file.clone());
+        }
+
+        logger::configure(&config)?;
+        *self.config.write().unwrap() = config;
+        Ok(())
+    }
+
+    // run a function over the data of the ith client, if there is any.
+    pub fn with_client<T>(&self, index: usize, f: impl FnOnce(&mut ClientInfo) -> T) -> Option<T> {
+        self.clients_info.lock().unwrap()[index].as_mut().map(f)
+    }
+
+    pub fn is_operator(&self, index: usize) -> bool {
+        self.with_client(index, |info| info.operator).unwrap_or(false)
+    }
+
+    // call every registered hook. The list is copied first, so no lock is
+    // held while the hooks run.
+    pub fn run_hooks(&self, f: impl Fn(&dyn EventHook)) {
+        let hooks = self.hooks.read().unwrap().clone();
+        for hook in hooks {
+            f(hook.as_ref());
+        }
+    }
+
+    // pass a text through the hooks, in order. Returns the text left by the
+    // last hook, or the reason of the first veto.
+    pub fn filter_through_hooks(
+        &self,
+        text: &str,
+        f: impl Fn(&dyn EventHook, &str) -> Verdict,
+    ) -> Result<String, String> {
+        let hooks = self.hooks.read().unwrap().clone();
+        let mut text = text.to_string();
+        for hook in hooks {
+            match f(hook.as_ref(), &text) {
+                Verdict::Continue => {}
+                Verdict::Replace(replaced) => text = replaced,
+                Verdict::Veto(reason) => return Err(reason),
+            }
+        }
+        Ok(text)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+}
+
+// counters of the server activity since it started.
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    pub connections_accepted: AtomicU64,
+    pub connections_rejected: AtomicU64,
+    pub messages_received: AtomicU64,
+    pub messages_broadcast: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub commands: Mutex<BTreeMap<String, u64>>, // times every command was used
+}
+
+impl ServerStats {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add(counter: &AtomicU64, amount: usize) {
+        counter.fetch_add(amount as u64, Ordering::Relaxed);
+    }
+
+    pub fn count_command(&self, command: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(command.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+// copy of the server counters at a given time (see `ServerHandle::stats`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub uptime: Duration,
+    pub connected_clients: usize,
+    pub joined_users: usize,
+    pub connections_accepted: u64,
+    pub connections_rejected: u64,
+    pub messages_received: u64,
+    pub messages_broadcast: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub commands: BTreeMap<String, u64>,
+}
+
+// data of a connected client.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64, // unique for every connection, unlike the position in the arrays
+    pub address: SocketAddr,
+    pub connected_at: Instant,
+    pub joined_at: Option<Instant>,
+    pub last_activity: Instant,
+    pub operator: bool,
+    pub mute: Option<Mute>,
+    pub away: Option<String>, // message given with AWAY, maybe empty
+    // told to the others when the client leaves, instead of `<nick> has left
+    // the chat`, i.e.: after a KICK
+    pub leave_notice: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn new(id: u64, address: SocketAddr, operator: bool) -> ClientInfo {
+        let now = Instant::now();
+        ClientInfo {
+            id,
+            address,
+            connected_at: now,
+            joined_at: None,
+            last_activity: now,
+            operator,
+            mute: None,
+            away: None,
+            leave_notice: None,
+        }
+    }
+
+    // time left until the client can talk again, if an operator muted it.
+    // Indefinite mutes report Duration::MAX.
+    pub fn muted_for(&self, now: Instant) -> Option<Duration> {
+        match self.mute {
+            Some(Mute::Until(until)) if until > now => Some(until - now),
+            Some(Mute::Indefinitely) => Some(Duration::MAX),
+            _ => None,
+        }
+    }
+
+    // the client is away if it said so, or idle if it did not send anything
+    // in `idle_after`. A zero `idle_after` disables the idle detection.
+    pub fn presence(&self, now: Instant, idle_after: Duration) -> Presence {
+        match &self.away {
+            Some(message) => Presence::Away(message.clone()),
+            None if !idle_after.is_zero() && now - self.last_activity >= idle_after => {
+                Presence::Idle
+            }
+            None => Presence::Active,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Presence {
+    Active,
+    Idle,
+    Away(String),
+}
+
+impl fmt::Display for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Presence::Active => "active",
+            Presence::Idle => "idle",
+            Presence::Away(_) => "away",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mute {
+    Until(Instant),
+    Indefinitely,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_presence() {
+        let mut info = ClientInfo::new(1, "127.0.0.1:5000".parse().unwrap(), false);
+        let idle_after = Duration::from_secs(60);
+        let now = info.last_activity;
+        assert_eq!(info.presence(now, idle_after), Presence::Active);
+        let later = now + idle_after;
+        assert_eq!(info.presence(later, idle_after), Presence::Idle);
+        assert_eq!(info.presence(later, Duration::ZERO), Presence::Active);
+        info.away = Some(String::from("lunch"));
+        assert_eq!(
+            info.presence(now, idle_after),
+            Presence::Away(String::from("lunch"))
+        );
+    }
+}
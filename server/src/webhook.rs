@@ -0,0 +1,238 @@
+// outgoing webhook: chat messages POSTed as JSON to an HTTP endpoint.
+//
+// When `webhook_url` is configured, every message broadcast to the chat, or
+// only the ones matching `webhook_pattern`, is sent to that URL as:
+//
+//   POST /hook HTTP/1.1
+//   Content-Type: application/json
+//
+//   {"nick":"bob","text":"deploy done","time":"2024-05-01T10:20:30Z"}
+//
+// The requests are sent by a separate thread, from a bounded queue, so a slow
+// endpoint never blocks the chat: when the queue is full, new messages are
+// dropped. A request failing, or answered with a status other than 2xx, is
+// retried up to `webhook_retries` times, waiting longer after every attempt.
+//
+// reference: https://datatracker.ietf.org/doc/html/rfc9112
+
+use crate::aux::{glob_match, json_string};
+use crate::logger;
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// time to connect, send the request and read the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// wait before the first retry, doubled after every failed attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+// an `http://host[:port]/path` URL. HTTPS is not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl FromStr for WebhookUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WebhookUrl, String> {
+        let rest = s
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("`{}` is not an http:// URL", s))?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        // an IPv6 address is in brackets, i.e.: http://[::1]:8080/
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => return Err(format!("expected `:` after `]` in `{}`", s)),
+                },
+                None => return Err(format!("unclosed `[` in `{}`", s)),
+            },
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("invalid port `{}` in `{}`", port, s))?,
+            None => 80,
+        };
+        if host.is_empty() {
+            return Err(format!("no host in `{}`", s));
+        }
+        Ok(WebhookUrl {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for WebhookUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host_name(), self.port, self.path)
+    }
+}
+
+impl WebhookUrl {
+    // the host as written in a URL, with an IPv6 address in brackets.
+    fn host_name(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+
+    // the value of the `Host` header: the host, and the port unless it is
+    // the default one.
+    fn host_header(&self) -> String {
+        match self.port {
+            80 => self.host_name(),
+            port => format!("{}:{}", self.host_name(), port),
+        }
+    }
+}
+
+// the sending side of the webhook, kept by the server.
+pub struct Webhook {
+    pattern: Option<String>,
+    queue: SyncSender<String>,
+}
+
+impl Webhook {
+    // start the thread sending the requests.
+    pub fn spawn(
+        url: WebhookUrl,
+        pattern: Option<String>,
+        queue_size: usize,
+        retries: u32,
+    ) -> Webhook {
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        thread::spawn(move || send_requests(&url, retries, receiver));
+        Webhook {
+            pattern,
+            queue: sender,
+        }
+    }
+
+    // queue a chat message, if it matches the pattern.
+    pub fn post(&self, nick: &str, text: &str) {
+        if let Some(pattern) = &self.pattern {
+            if !glob_match(pattern, text) {
+                return;
+            }
+        }
+        let body = format!(
+            "{{\"nick\":{},\"text\":{},\"time\":{}}}",
+            json_string(nick),
+            json_string(text),
+            json_string(&logger::format_timestamp(SystemTime::now()))
+        );
+        match self.queue.try_send(body) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => logger::warn("webhook_dropped", &[("nick", &nick)]),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+fn send_requests(url: &WebhookUrl, retries: u32, queue: Receiver<String>) {
+    for body in queue {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match send_request(url, &body) {
+                Ok(200..=299) => break,
+                Ok(status) => logger::warn("webhook_failed", &[("url", url), ("status", &status)]),
+                Err(error) => logger::warn("webhook_failed", &[("url", url), ("error", &error)]),
+            }
+            if attempt == retries {
+                logger::error(
+                    "webhook_dropped",
+                    &[("url", url), ("attempts", &(attempt + 1))],
+                );
+                break;
+            }
+            attempt += 1;
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+}
+
+// POST the body, returning the status code of the response.
+fn send_request(url: &WebhookUrl, body: &str) -> io::Result<u16> {
+    let address = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("cannot resolve {}", url.host)))?;
+    let mut stream = TcpStream::connect_timeout(&address, REQUEST_TIMEOUT)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        url.path,
+        url.host_header(),
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes())?;
+
+    // i.e.: HTTP/1.1 204 No Content
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("invalid response {:?}", status_line.trim_end())))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_parse_webhook_url() {
+        let url: WebhookUrl = "http://hooks.local:8080/chat/in".parse().unwrap();
+        assert_eq!(url.host, "hooks.local");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/chat/in");
+        assert_eq!(url.host_header(), "hooks.local:8080");
+
+        let url: WebhookUrl = "http://hooks.local".parse().unwrap();
+        assert_eq!(url.to_string(), "http://hooks.local:80/");
+        assert_eq!(url.host_header(), "hooks.local");
+
+        assert!("https://hooks.local/".parse::<WebhookUrl>().is_err());
+        assert!("http://hooks.local:http/".parse::<WebhookUrl>().is_err());
+        assert!("http:///path".parse::<WebhookUrl>().is_err());
+
+        let url: WebhookUrl = "http://[::1]:8080/chat".parse().unwrap();
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.to_string(), "http://[::1]:8080/chat");
+        assert_eq!(url.host_header(), "[::1]:8080");
+        let url: WebhookUrl = "http://[::1]".parse().unwrap();
+        assert_eq!(url.port, 80);
+        assert!("http://[::1/".parse::<WebhookUrl>().is_err());
+        assert!("http://[::1]8080/".parse::<WebhookUrl>().is_err());
+    }
+}
@@ -0,0 +1,384 @@
+// WebSocket gateway, for the browser clients.
+//
+// When `websocket_address` is configured, the server accepts WebSocket
+// connections on that address too. Every text frame from the browser is a
+// line of the chat protocol, a message or a command, and every line from the
+// server is sent back as a text frame, i.e.:
+//
+//   const chat = new WebSocket("ws://localhost:8080/");
+//   chat.onmessage = (event) => console.log(event.data);
+//   chat.onopen = () => chat.send("JOIN alice");
+//
+// Each WebSocket connection is bridged to a chat connection over loopback,
+// admitted like any other client but with the address of the browser, so the
+// bans, the limits and the user list are shared with the TCP users.
+//
+// reference: https://datatracker.ietf.org/doc/html/rfc6455
+
+use crate::admit_client;
+use crate::logger;
+use crate::ClientsNameArray;
+use crate::ClientsStreamArray;
+use crate::SharedState;
+use crate::MAX_MESSAGE_SIZE;
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// appended to the key of the client to compute the accept key.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// bigger messages are refused: the chat ones are limited anyway.
+const MAX_FRAME_SIZE: usize = 4 * MAX_MESSAGE_SIZE;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// close status codes.
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+// accept WebSocket connections in a separate thread. Every connection gets
+// its own threads too.
+pub fn spawn_websocket_gateway(
+    address: SocketAddr,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    logger::info("websocket_listen", &[("address", &address)]);
+
+    let clients_array = Arc::clone(clients_array);
+    let stream_array = Arc::clone(stream_array);
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let clients_array = Arc::clone(&clients_array);
+            let stream_array = Arc::clone(&stream_array);
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                if let Err(error) = serve_connection(stream, &clients_array, &stream_array, &state)
+                {
+                    logger::debug("websocket_failed", &[("error", &error)]);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    clients_array: &ClientsNameArray,
+    stream_array: &ClientsStreamArray,
+    state: &SharedState,
+) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut stream = stream;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // the handshake has to arrive quickly, the chat messages do not
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    handshake(&mut reader, &mut stream)?;
+    stream.set_read_timeout(None)?;
+    let writer = Arc::new(Mutex::new(Browser {
+        stream,
+        closed: false,
+    }));
+
+    // a connected pair of sockets: one end is the chat client, the other
+    // one is bridged to the browser. Any local program can connect to the
+    // listener meanwhile, so only the bridge is taken
+    let chat = {
+        let pair = TcpListener::bind("127.0.0.1:0")?;
+        let bridge = TcpStream::connect(pair.local_addr()?)?;
+        let chat = loop {
+            let (chat, address) = pair.accept()?;
+            if address == bridge.local_addr()? {
+                break chat;
+            }
+        };
+        admit_client(chat, peer, clients_array, stream_array, state);
+        bridge
+    };
+    logger::debug("websocket_connect", &[("peer", &peer)]);
+
+    // lines from the chat, to the browser
+    let to_browser = {
+        let chat = BufReader::new(chat.try_clone()?);
+        let writer = Arc::clone(&writer);
+        thread::spawn(move || {
+            for line in chat.split(b'\n') {
+                let Ok(line) = line else { break };
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim_end_matches('\r');
+                if writer
+                    .lock()
+                    .unwrap()
+                    .send(OPCODE_TEXT, text.as_bytes())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            // the chat connection ended: LEAVE, a kick, or a shutdown
+            let mut writer = writer.lock().unwrap();
+            let _ = writer.close(CLOSE_NORMAL);
+            let _ = writer.stream.shutdown(Shutdown::Both);
+        })
+    };
+
+    // frames from the browser, to the chat
+    let result = relay_frames(&mut reader, &writer, &chat);
+    let _ = chat.shutdown(Shutdown::Both);
+    let _ = to_browser.join();
+    logger::debug("websocket_disconnect", &[("peer", &peer)]);
+    result
+}
+
+// read the opening handshake, and accept it.
+fn handshake(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut key = None;
+    let mut upgrade = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "sec-websocket-key" => key = Some(value.to_string()),
+                "upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+                _ => {}
+            }
+        }
+    }
+
+    match key {
+        Some(key) if request_line.starts_with("GET ") && upgrade => {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)
+            );
+            writer.write_all(response.as_bytes())
+        }
+        _ => {
+            let body = "a WebSocket handshake was expected\n";
+            let response = format!(
+                "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            writer.write_all(response.as_bytes())?;
+            Err(io::Error::other("not a WebSocket handshake"))
+        }
+    }
+}
+
+// the key sent back to the client, proving the handshake was understood.
+pub fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key, HANDSHAKE_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+// the connection to the browser, shared by the threads writing to it.
+struct Browser {
+    stream: TcpStream,
+    closed: bool, // a close frame was sent, nothing can follow it
+}
+
+impl Browser {
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::other("the WebSocket is closed"));
+        }
+        write_frame(&mut self.stream, opcode, payload)
+    }
+
+    // send the close frame, once.
+    fn close(&mut self, code: u16) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        write_close(&mut self.stream, code)
+    }
+}
+
+// send the text frames to the chat, one line each, and answer the control
+// frames, until the browser closes the connection.
+fn relay_frames(
+    reader: &mut impl Read,
+    writer: &Mutex<Browser>,
+    chat: &TcpStream,
+) -> io::Result<()> {
+    let mut chat = chat;
+    let mut message: Vec<u8> = Vec::new();
+    let mut in_message = false; // a text frame without FIN was received
+    loop {
+        let frame = match read_frame(reader)? {
+            Ok(frame) => frame,
+            Err(code) => return writer.lock().unwrap().close(code),
+        };
+        match frame.opcode {
+            // a continuation only follows a started message, and a new
+            // message only starts after the last one finished
+            OPCODE_TEXT | OPCODE_CONTINUATION
+                if in_message != (frame.opcode == OPCODE_CONTINUATION) =>
+            {
+                return writer.lock().unwrap().close(CLOSE_PROTOCOL_ERROR)
+            }
+            OPCODE_TEXT | OPCODE_CONTINUATION => {
+                in_message = !frame.fin;
+                message.extend_from_slice(&frame.payload);
+                if message.len() > MAX_FRAME_SIZE {
+                    return writer.lock().unwrap().close(CLOSE_TOO_BIG);
+                }
+                if frame.fin {
+                    // a line break inside the message would split it in two
+                    let line: Vec<u8> = message
+                        .drain(..)
+                        .map(|byte| if byte == b'\n' { b' ' } else { byte })
+                        .chain([b'\n'])
+                        .collect();
+                    chat.write_all(&line)?;
+                }
+            }
+            OPCODE_BINARY => return writer.lock().unwrap().close(CLOSE_UNSUPPORTED_DATA),
+            OPCODE_PING => writer.lock().unwrap().send(OPCODE_PONG, &frame.payload)?,
+            OPCODE_PONG => {}
+            OPCODE_CLOSE => return writer.lock().unwrap().close(CLOSE_NORMAL),
+            _ => return writer.lock().unwrap().close(CLOSE_PROTOCOL_ERROR),
+        }
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// read a frame from the client. The inner error is the close code to answer
+// a frame breaking the protocol with.
+fn read_frame(reader: &mut impl Read) -> io::Result<Result<Frame, u16>> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut length = [0; 2];
+            reader.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as u64
+        }
+        127 => {
+            let mut length = [0; 8];
+            reader.read_exact(&mut length)?;
+            u64::from_be_bytes(length)
+        }
+        length => length as u64,
+    };
+    // the clients must mask their frames, and the control frames (close,
+    // ping and pong) are short and not fragmented
+    if !masked || (opcode & 0x8 != 0 && (!fin || length > 125)) {
+        return Ok(Err(CLOSE_PROTOCOL_ERROR));
+    }
+    if length > MAX_FRAME_SIZE as u64 {
+        return Ok(Err(CLOSE_TOO_BIG));
+    }
+
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Ok(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+// write a single, unmasked, frame.
+pub fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+fn write_close(writer: &mut impl Write, code: u16) -> io::Result<()> {
+    write_frame(writer, OPCODE_CLOSE, &code.to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn verify_accept_key() {
+        // the example of the RFC
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn verify_read_and_write_frames() {
+        let mut written = Vec::new();
+        write_frame(&mut written, OPCODE_TEXT, b"Hello").unwrap();
+        assert_eq!(written, [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']);
+
+        // a masked "Hello", from the RFC
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = read_frame(&mut &masked[..]).unwrap().unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OPCODE_TEXT);
+        assert_eq!(frame.payload, b"Hello");
+
+        // unmasked frames are refused
+        assert_eq!(read_frame(&mut &written[..]).unwrap().err(), Some(1002));
+
+        // and so are fragmented or long control frames
+        let ping = [0x09, 0x80, 0, 0, 0, 0];
+        assert_eq!(read_frame(&mut &ping[..]).unwrap().err(), Some(1002));
+        let mut ping = vec![0x89, 0xFE, 0x00, 0x7E, 0, 0, 0, 0];
+        ping.extend([0; 126]);
+        assert_eq!(read_frame(&mut &ping[..]).unwrap().err(), Some(1002));
+    }
+}
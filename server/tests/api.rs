@@ -0,0 +1,113 @@
+// tests of the HTTP API: posting messages and listing the users.
+
+mod common;
+
+use common::{free_address, start_server_with, TestClient};
+use server::ServerConfig;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+const TOKEN: &str = "s3cret";
+
+fn start_api() -> (SocketAddr, SocketAddr) {
+    let api = free_address();
+    let address = start_server_with(ServerConfig {
+        api_address: Some(api),
+        api_token: Some(String::from(TOKEN)),
+        ..Default::default()
+    });
+    (address, api)
+}
+
+// send a request, returning the status code and the body of the response.
+fn request(api: SocketAddr, method: &str, path: &str, token: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(api).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        token,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, body.to_string())
+}
+
+#[test]
+fn messages_are_posted_to_the_chat() {
+    let (address, api) = start_api();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    let (status, _) = request(api, "POST", "/rooms/main/messages", TOKEN, "deploy done\n");
+    assert_eq!(status, 200);
+    alice.expect_line("*** deploy done");
+    bob.expect_line("*** deploy done");
+
+    let (status, _) = request(api, "POST", "/rooms/main/messages", TOKEN, "one\ntwo");
+    assert_eq!(status, 400);
+    let (status, _) = request(api, "POST", "/rooms/ops/messages", TOKEN, "hi");
+    assert_eq!(status, 404);
+    alice.expect_nothing();
+}
+
+#[test]
+fn users_are_listed() {
+    let (address, api) = start_api();
+    let _alice = TestClient::join(address, "alice");
+    let _stranger = TestClient::connect(address);
+
+    assert_eq!(
+        request(api, "GET", "/users", TOKEN, ""),
+        (200, String::from("[\"alice\"]\n"))
+    );
+    assert_eq!(request(api, "DELETE", "/users", TOKEN, "").0, 405);
+}
+
+#[test]
+fn requests_need_the_token() {
+    let (address, api) = start_api();
+    let mut alice = TestClient::join(address, "alice");
+
+    let (status, _) = request(api, "POST", "/rooms/main/messages", "guess", "hi");
+    assert_eq!(status, 401);
+    assert_eq!(request(api, "GET", "/users", "", "").0, 401);
+    alice.expect_nothing();
+}
+
+#[test]
+fn the_api_cannot_run_without_a_token() {
+    let config = ServerConfig {
+        api_address: Some(free_address()),
+        ..Default::default()
+    };
+    assert!(server::Server::bind("127.0.0.1:0", config).is_err());
+}
+
+#[test]
+fn a_stalled_request_does_not_block_the_others() {
+    let (address, api) = start_api();
+    let _alice = TestClient::join(address, "alice");
+
+    // a request that never sends its body
+    let mut stalled = TcpStream::connect(api).unwrap();
+    write!(
+        stalled,
+        "POST /rooms/main/messages HTTP/1.1\r\nContent-Length: 10\r\n\r\n"
+    )
+    .unwrap();
+
+    let started = Instant::now();
+    let (status, _) = request(api, "GET", "/users", TOKEN, "");
+    assert_eq!(status, 200);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
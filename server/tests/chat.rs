@@ -0,0 +1,97 @@
+// end to end tests of the chat protocol: a server on a free port, and
+// scripted clients talking to it.
+
+mod common;
+
+use common::{start_server, TestClient};
+
+#[test]
+fn join_is_announced_to_the_others() {
+    let address = start_server();
+    let mut alice = TestClient::join(address, "alice");
+    let _bob = TestClient::join(address, "bob");
+
+    alice.expect_line("bob has joined the chat");
+}
+
+#[test]
+fn welcome_reply_counts_the_users() {
+    let address = start_server();
+    let mut alice = TestClient::connect(address);
+    alice.send("JOIN alice");
+    alice.expect_line(">>> welcome to the chat, alice! you are the only user here");
+    let mut bob = TestClient::connect(address);
+    bob.send("JOIN bob");
+    bob.expect_line(">>> welcome to the chat, bob! there are 2 users here");
+}
+
+#[test]
+fn messages_are_broadcast_to_the_others() {
+    let address = start_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    let mut carol = TestClient::join(address, "carol");
+    alice.read_until(|line| line == "carol has joined the chat");
+    bob.read_until(|line| line == "carol has joined the chat");
+
+    alice.send("hello everybody");
+    bob.expect_line("[alice] hello everybody");
+    carol.expect_line("[alice] hello everybody");
+    alice.expect_nothing();
+}
+
+#[test]
+fn messages_before_join_are_not_broadcast() {
+    let address = start_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut stranger = TestClient::connect(address);
+
+    stranger.send("anybody here?");
+    alice.expect_nothing();
+}
+
+#[test]
+fn who_lists_the_users() {
+    let address = start_server();
+    let mut alice = TestClient::join(address, "alice");
+    let _bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    alice.send("WHO");
+    let users = alice.read_response("WHO");
+    assert_eq!(users.len(), 2);
+    assert!(users[0].starts_with("alice "));
+    assert!(users[1].starts_with("bob "));
+
+    alice.send("WHO b*");
+    let users = alice.read_response("WHO");
+    assert_eq!(users.len(), 1);
+    assert!(users[0].starts_with("bob "));
+}
+
+#[test]
+fn leave_is_announced_and_closes_the_connection() {
+    let address = start_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    bob.send("LEAVE");
+    bob.expect_closed();
+    alice.expect_line("bob has left the chat");
+
+    alice.send("WHO");
+    assert_eq!(alice.read_response("WHO").len(), 1);
+}
+
+#[test]
+fn names_cannot_be_taken_twice() {
+    let address = start_server();
+    let _alice = TestClient::join(address, "alice");
+    let mut other = TestClient::connect(address);
+
+    other.send("JOIN ALICE");
+    other.expect_line(">>> the name ALICE is already in use");
+    other.send("JOIN alicia");
+    other.read_until(|line| line.starts_with(">>> welcome to the chat, alicia!"));
+}
@@ -0,0 +1,157 @@
+// helpers for the integration tests: start a server on a free port, and
+// connect scripted clients to it.
+
+#![allow(dead_code)] // every test file uses a different part of the harness
+
+use server::logger::Level;
+use server::{Server, ServerConfig};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+// time to wait for a line from the server before failing the test.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// time to wait when checking that the server does NOT send anything.
+const QUIET_TIMEOUT: Duration = Duration::from_millis(200);
+
+// start a server with the default configuration, returning its address.
+pub fn start_server() -> SocketAddr {
+    start_server_with(ServerConfig::default())
+}
+
+pub fn start_server_with(config: ServerConfig) -> SocketAddr {
+    let server = bind_server(config);
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    address
+}
+
+// bind a server to a free port, without running it yet.
+pub fn bind_server(config: ServerConfig) -> Server {
+    // keep the test output clean
+    let config = ServerConfig {
+        log_level: Level::Error,
+        ..config
+    };
+    server::logger::configure(&config).unwrap();
+
+    Server::bind("127.0.0.1:0", config).expect("the server cannot bind")
+}
+
+// a local address that is free, for the servers that need one besides the
+// chat, such as the API.
+pub fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+}
+
+pub struct TestClient {
+    name: String,
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    // connect to the server, and skip the welcome banner and MOTD.
+    pub fn connect(address: SocketAddr) -> TestClient {
+        let stream = TcpStream::connect(address).expect("cannot connect to the server");
+        stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+        let mut client = TestClient {
+            name: String::from("-"),
+            writer: stream.try_clone().unwrap(),
+            reader: BufReader::new(stream),
+        };
+        client.read_until(|line| line.starts_with(">>> use JOIN"));
+        client
+    }
+
+    // connect and JOIN the chat with the given name.
+    pub fn join(address: SocketAddr, name: &str) -> TestClient {
+        let mut client = TestClient::connect(address);
+        client.name = name.to_string();
+        client.send(&format!("JOIN {}", name));
+        client.read_until(|line| line.starts_with(">>> welcome to the chat"));
+        client
+    }
+
+    pub fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .expect("cannot send to the server");
+    }
+
+    // read the next line, without the line break. Fails the test if nothing
+    // arrives in time.
+    pub fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => panic!("{}: the server closed the connection", self.name),
+            Ok(_) => line.trim_end_matches(['\r', '\n']).to_string(),
+            Err(error) => panic!("{}: nothing received from the server: {}", self.name, error),
+        }
+    }
+
+    pub fn expect_line(&mut self, expected: &str) {
+        assert_eq!(self.read_line(), expected, "{}: unexpected line", self.name);
+    }
+
+    // read lines until one matches, returning it.
+    pub fn read_until(&mut self, matches: impl Fn(&str) -> bool) -> String {
+        loop {
+            let line = self.read_line();
+            if matches(&line) {
+                return line;
+            }
+        }
+    }
+
+    // read the lines of a framed response, such as WHO, without the begin
+    // and end lines.
+    pub fn read_response(&mut self, name: &str) -> Vec<String> {
+        let begin = format!(">>> {} BEGIN", name);
+        let end = format!(">>> {} END ", name);
+        self.read_until(|line| line == begin);
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line();
+            if let Some(count) = line.strip_prefix(&end) {
+                assert_eq!(count, lines.len().to_string(), "wrong count in {}", line);
+                return lines;
+            }
+            lines.push(line);
+        }
+    }
+
+    // check that the server sends nothing for a while.
+    pub fn expect_nothing(&mut self) {
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(QUIET_TIMEOUT))
+            .unwrap();
+        let mut line = String::new();
+        let received = self.reader.read_line(&mut line);
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(READ_TIMEOUT))
+            .unwrap();
+        if let Ok(size) = received {
+            assert!(size == 0, "{}: unexpected line {:?}", self.name, line);
+        }
+    }
+
+    // check that the server closed the connection.
+    pub fn expect_closed(&mut self) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return,
+                Ok(_) => continue, // a goodbye message
+                Err(error) => panic!("{}: the connection is still open: {}", self.name, error),
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// tests of the library API: hooks, the server handle and shutdown.
+
+mod common;
+
+use common::{bind_server, TestClient};
+use server::{CommandContext, CommandHandler, EventHook, ServerConfig, Verdict, MAX_CLIENTS};
+
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// hook that records every event it gets.
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<String>>,
+}
+
+impl Recorder {
+    fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl EventHook for Recorder {
+    fn on_join(&self, nick: &str) {
+        self.events.lock().unwrap().push(format!("join {}", nick));
+    }
+
+    fn on_leave(&self, nick: &str) {
+        self.events.lock().unwrap().push(format!("leave {}", nick));
+    }
+
+    fn on_message(&self, nick: &str, text: &str) -> Verdict {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("message {} {}", nick, text));
+        Verdict::Continue
+    }
+
+    fn on_command(&self, nick: Option<&str>, line: &str) -> Verdict {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("command {} {}", nick.unwrap_or("-"), line));
+        Verdict::Continue
+    }
+}
+
+// hook that moderates the chat: it filters words, stops spam and private
+// messages, and lists only the users whose name starts with a b.
+struct Moderator;
+
+impl EventHook for Moderator {
+    fn on_message(&self, _nick: &str, text: &str) -> Verdict {
+        if text.contains("spam") {
+            Verdict::Veto(String::from("no spam, please"))
+        } else if text.starts_with("psst") {
+            Verdict::Veto(String::new())
+        } else if text.contains("darn") {
+            Verdict::Replace(text.replace("darn", "****"))
+        } else {
+            Verdict::Continue
+        }
+    }
+
+    fn on_command(&self, _nick: Option<&str>, line: &str) -> Verdict {
+        if line.starts_with("MSG ") {
+            Verdict::Veto(String::from("private messages are disabled"))
+        } else if line == "WHO" {
+            Verdict::Replace(String::from("WHO b*"))
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+// custom command that sends its text, in capitals, to everybody.
+struct Shout;
+
+impl CommandHandler for Shout {
+    fn name(&self) -> &str {
+        "SHOUT"
+    }
+
+    fn usage(&self) -> &str {
+        "SHOUT <text>"
+    }
+
+    fn help(&self) -> &str {
+        "say something loud"
+    }
+
+    fn handle(&self, args: &str, context: &CommandContext) {
+        let nick = context.nick().unwrap_or_default();
+        context.broadcast(&format!("{} shouts {}", nick, args.to_uppercase()));
+    }
+}
+
+// custom command reserved to operators.
+struct Deploy;
+
+impl CommandHandler for Deploy {
+    fn name(&self) -> &str {
+        "DEPLOY"
+    }
+
+    fn help(&self) -> &str {
+        "deploy the latest version"
+    }
+
+    fn operator_only(&self) -> bool {
+        true
+    }
+
+    fn handle(&self, _args: &str, context: &CommandContext) {
+        context.reply(">>> deploying");
+    }
+}
+
+#[test]
+fn custom_commands_can_be_registered() {
+    let mut server = bind_server(ServerConfig::default());
+    server.add_command(Arc::new(Shout)).unwrap();
+    server.add_command(Arc::new(Deploy)).unwrap();
+    assert!(server.add_command(Arc::new(Shout)).is_err());
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    let mut stranger = TestClient::connect(address);
+    stranger.send("SHOUT hello");
+    stranger.expect_line(">>> JOIN the chat before using SHOUT");
+
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+    bob.send("SHOUT hello");
+    alice.expect_line("bob shouts HELLO");
+    bob.expect_line("bob shouts HELLO");
+
+    bob.send("DEPLOY");
+    bob.expect_line(">>> permission denied: you are not an operator");
+
+    bob.send("HELP");
+    let help = bob.read_response("HELP");
+    assert!(help.iter().any(|line| line.starts_with("WHO [pattern] ")));
+    assert!(help.iter().any(|line| line.starts_with("SHOUT <text> ")));
+    assert!(!help.iter().any(|line| line.starts_with("DEPLOY")));
+    assert!(!help.iter().any(|line| line.starts_with("KICK")));
+}
+
+#[test]
+fn hooks_are_told_about_the_chat_events() {
+    let recorder = Arc::new(Recorder::default());
+    let mut server = bind_server(ServerConfig::default());
+    server.add_hook(recorder.clone());
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+    bob.send("hi alice");
+    alice.expect_line("[bob] hi alice");
+    bob.send("LEAVE");
+    bob.expect_closed();
+    alice.expect_line("bob has left the chat");
+
+    assert_eq!(
+        recorder.events(),
+        [
+            "command - JOIN alice",
+            "join alice",
+            "command - JOIN bob",
+            "join bob",
+            "message bob hi alice",
+            "command bob LEAVE",
+            "leave bob"
+        ]
+    );
+}
+
+#[test]
+fn hooks_can_veto_or_replace_messages_and_commands() {
+    let recorder = Arc::new(Recorder::default());
+    let mut server = bind_server(ServerConfig::default());
+    server.add_hook(Arc::new(Moderator));
+    server.add_hook(recorder.clone());
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    bob.send("buy spam now");
+    bob.expect_line(">>> no spam, please");
+    bob.send("psst alice");
+    bob.send("darn it");
+    alice.expect_line("[bob] **** it");
+    bob.expect_nothing();
+
+    bob.send("MSG alice hello");
+    bob.expect_line(">>> private messages are disabled");
+    alice.expect_nothing();
+
+    alice.send("WHO");
+    let users = alice.read_response("WHO");
+    assert_eq!(users.len(), 1);
+    assert!(users[0].starts_with("bob "));
+
+    // the hooks after a veto are not called, and see the replaced text
+    let events = recorder.events();
+    assert!(events.contains(&String::from("message bob **** it")));
+    assert!(events.contains(&String::from("command alice WHO b*")));
+    assert!(!events.iter().any(|event| event.contains("spam")));
+    assert!(!events.iter().any(|event| event.contains("MSG")));
+}
+
+#[test]
+fn say_is_always_answered() {
+    let mut server = bind_server(ServerConfig::default());
+    server.add_hook(Arc::new(Moderator));
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    let mut bob = TestClient::connect(address);
+    bob.send("SAY hello");
+    bob.expect_line(">>> SAY REFUSED join the chat first");
+
+    let mut alice = TestClient::join(address, "alice");
+    bob.send("JOIN bob");
+    alice.expect_line("bob has joined the chat");
+    bob.read_until(|line| line.starts_with(">>> welcome to the chat"));
+
+    // the text is sent as it is, even if it looks like a command
+    bob.send("SAY WHO is here?");
+    bob.expect_line(">>> SAY OK");
+    alice.expect_line("[bob] WHO is here?");
+
+    bob.send("SAY buy spam now");
+    bob.expect_line(">>> SAY REFUSED no spam, please");
+    bob.send("SAY psst alice");
+    bob.expect_line(">>> SAY REFUSED the message was refused");
+    alice.expect_nothing();
+}
+
+#[test]
+fn the_handle_reports_users_and_stats() {
+    let server = bind_server(ServerConfig::default());
+    let address = server.local_addr().unwrap();
+    let handle = server.handle();
+    thread::spawn(move || server.run());
+
+    let mut alice = TestClient::join(address, "alice");
+    let _stranger = TestClient::connect(address);
+    alice.send("WHO");
+    alice.read_response("WHO");
+
+    assert_eq!(handle.users(), ["alice"]);
+    let stats = handle.stats();
+    assert_eq!(stats.connected_clients, 2);
+    assert_eq!(stats.joined_users, 1);
+    assert_eq!(stats.connections_accepted, 2);
+    assert_eq!(stats.commands.get("WHO"), Some(&1));
+}
+
+#[test]
+fn a_full_server_rejects_the_connection() {
+    let server = bind_server(ServerConfig {
+        max_connections_per_ip: 0,
+        ..Default::default()
+    });
+    let address = server.local_addr().unwrap();
+    let handle = server.handle();
+    thread::spawn(move || server.run());
+
+    let _clients: Vec<TestClient> = (0..MAX_CLIENTS)
+        .map(|_| TestClient::connect(address))
+        .collect();
+    let mut reply = String::new();
+    BufReader::new(TcpStream::connect(address).unwrap())
+        .read_line(&mut reply)
+        .unwrap();
+    assert_eq!(reply, ">>> the server is full, try again later\n");
+    assert_eq!(handle.stats().connections_rejected, 1);
+}
+
+#[test]
+fn shutdown_stops_the_server() {
+    let server = bind_server(ServerConfig::default());
+    let address = server.local_addr().unwrap();
+    let handle = server.handle();
+    let running = thread::spawn(move || server.run());
+
+    let mut alice = TestClient::join(address, "alice");
+    handle.shutdown();
+
+    alice.expect_line(">>> the server is shutting down");
+    alice.expect_closed();
+    assert!(running.join().unwrap().is_ok());
+    assert!(TcpStream::connect(address).is_err());
+}
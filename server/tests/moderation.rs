@@ -0,0 +1,146 @@
+// end to end tests of the moderation: bans and the operator commands.
+
+mod common;
+
+use common::{free_address, start_server_with, TestClient};
+use server::bans::BanPattern;
+use server::ServerConfig;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+// a server where the local clients are operators.
+fn operators_server() -> std::net::SocketAddr {
+    start_server_with(ServerConfig {
+        operator_hosts: vec![BanPattern::parse("127.0.0.1").unwrap()],
+        ..Default::default()
+    })
+}
+
+#[test]
+fn banned_users_leave_and_their_slot_can_be_reused() {
+    let address = operators_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.read_until(|line| line == "bob has joined the chat");
+
+    alice.send("BAN nick:bob");
+    bob.read_until(|line| line.starts_with(">>> you have been banned from this server"));
+    bob.expect_closed();
+    alice.read_until(|line| line == "bob has left the chat");
+
+    // the thread of bob is done with its slot, so the new client keeps it
+    let mut carol = TestClient::join(address, "carol");
+    alice.read_until(|line| line == "carol has joined the chat");
+    carol.expect_nothing();
+    carol.send("WHO");
+    let users = carol.read_response("WHO");
+    assert_eq!(users.len(), 2, "unexpected users {:?}", users);
+}
+
+#[test]
+fn nick_bans_cover_the_names_that_look_the_same() {
+    let address = operators_server();
+    let mut alice = TestClient::join(address, "alice");
+    alice.send("BAN nick:bob");
+    alice.read_until(|line| line.starts_with(">>> banned"));
+
+    let mut bob = TestClient::connect(address);
+    bob.send("JOIN ｂｏｂ"); // fullwidth letters
+    bob.read_until(|line| line.starts_with(">>> the name ｂｏｂ is banned from this server"));
+}
+
+#[test]
+fn oper_needs_the_password() {
+    let address = start_server_with(ServerConfig {
+        oper_password: Some(String::from("secret")),
+        ..Default::default()
+    });
+    let mut alice = TestClient::join(address, "alice");
+    let _bob = TestClient::join(address, "bob");
+    alice.read_until(|line| line == "bob has joined the chat");
+
+    alice.send("KICK bob");
+    alice.expect_line(">>> permission denied: you are not an operator");
+    alice.send("OPER guess");
+    alice.expect_line(">>> wrong password");
+    alice.send("OPER secret");
+    alice.expect_line(">>> you are now an operator");
+}
+
+#[test]
+fn kicked_users_are_disconnected_and_announced() {
+    let address = operators_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    let mut carol = TestClient::join(address, "carol");
+    alice.read_until(|line| line == "carol has joined the chat");
+    bob.read_until(|line| line == "carol has joined the chat");
+
+    alice.send("KICK bob too loud");
+    bob.expect_line(">>> you have been kicked by alice (too loud)");
+    bob.expect_closed();
+    carol.read_until(|line| line == "bob was kicked by alice (too loud)");
+    alice.read_until(|line| line == ">>> bob kicked");
+    // the kick is the only notice of the leave
+    carol.expect_nothing();
+
+    alice.send("KICK bob");
+    alice.read_until(|line| line == ">>> there is no user called bob");
+}
+
+#[test]
+fn muted_users_cannot_talk_until_unmuted() {
+    let address = operators_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.read_until(|line| line == "bob has joined the chat");
+
+    alice.send("MUTE bob");
+    alice.expect_line(">>> bob muted");
+    bob.expect_line(">>> you have been muted by alice");
+    bob.send("can I talk?");
+    bob.expect_line(">>> you are muted");
+    alice.expect_nothing();
+
+    alice.send("UNMUTE bob");
+    alice.expect_line(">>> bob unmuted");
+    bob.expect_line(">>> you have been unmuted by alice");
+    bob.send("thanks");
+    alice.expect_line("[bob] thanks");
+}
+
+#[test]
+fn announcements_reach_everybody() {
+    let address = operators_server();
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.read_until(|line| line == "bob has joined the chat");
+
+    alice.send("ANNOUNCE maintenance at 5");
+    bob.expect_line("*** ANNOUNCEMENT: maintenance at 5");
+    alice.expect_line("*** ANNOUNCEMENT: maintenance at 5");
+}
+
+#[test]
+fn the_administrator_kicks_by_position() {
+    let admin_address = free_address();
+    let address = start_server_with(ServerConfig {
+        admin_address: Some(admin_address),
+        ..Default::default()
+    });
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.read_until(|line| line == "bob has joined the chat");
+
+    let mut admin = TcpStream::connect(admin_address).unwrap();
+    admin.write_all(b"KICK 1\n").unwrap();
+    let mut output = String::new();
+    BufReader::new(&admin).read_line(&mut output).unwrap();
+    assert_eq!(output.trim_end(), "connection at position 1 kicked");
+
+    bob.expect_line(">>> you have been kicked by the administrator");
+    bob.expect_closed();
+    alice.read_until(|line| line == "bob has left the chat");
+    alice.expect_nothing();
+}
@@ -0,0 +1,96 @@
+// tests of the outgoing webhook, against a stub HTTP server.
+
+mod common;
+
+use common::{start_server_with, TestClient};
+use server::ServerConfig;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// start an HTTP server answering with the given statuses, in order, and then
+// with 200. Returns its URL, and the bodies of the requests it receives.
+fn start_stub(statuses: &[u16]) -> (String, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let statuses = statuses.to_vec();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut statuses = statuses.into_iter();
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let status = statuses.next().unwrap_or(200);
+            write!(
+                stream,
+                "HTTP/1.1 {} Stub\r\nContent-Length: 0\r\n\r\n",
+                status
+            )
+            .unwrap();
+            sender.send(String::from_utf8(body).unwrap()).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+fn webhook_config(url: &str, pattern: Option<&str>) -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.set("webhook_url", url).unwrap();
+    config.webhook_pattern = pattern.map(String::from);
+    config
+}
+
+#[test]
+fn matching_messages_are_posted() {
+    let (url, requests) = start_stub(&[]);
+    let address = start_server_with(webhook_config(&url, Some("*deploy*")));
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    bob.send("hello");
+    alice.expect_line("[bob] hello");
+    bob.send("deploy \"v2\" done");
+    alice.expect_line("[bob] deploy \"v2\" done");
+
+    let body = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(
+        body.starts_with(r#"{"nick":"bob","text":"deploy \"v2\" done","time":""#),
+        "unexpected body {}",
+        body
+    );
+    assert!(requests.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn failed_requests_are_retried() {
+    let (url, requests) = start_stub(&[503]);
+    let address = start_server_with(webhook_config(&url, None));
+    let mut alice = TestClient::join(address, "alice");
+    let mut bob = TestClient::join(address, "bob");
+    alice.expect_line("bob has joined the chat");
+
+    bob.send("build failed");
+    let first = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    let retry = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(first, retry);
+    assert!(first.contains(r#""text":"build failed""#));
+}
@@ -0,0 +1,206 @@
+// tests of the WebSocket gateway: browser users chatting with TCP users.
+
+mod common;
+
+use common::{free_address, start_server_with, TestClient};
+use server::ServerConfig;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// a minimal WebSocket client, as a browser would be.
+struct BrowserClient {
+    stream: TcpStream,
+}
+
+impl BrowserClient {
+    fn connect(address: SocketAddr) -> BrowserClient {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+
+        // read the response byte by byte, so no frame is buffered
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 "), "{}", response);
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        BrowserClient { stream }
+    }
+
+    // send a masked text frame.
+    fn send(&mut self, text: &str) {
+        self.send_frame(true, 1, text.as_bytes());
+    }
+
+    // send a masked frame, of less than 126 bytes.
+    fn send_frame(&mut self, fin: bool, opcode: u8, payload: &[u8]) {
+        let mask = [1, 2, 3, 4];
+        let fin = if fin { 0x80 } else { 0 };
+        let mut frame = vec![fin | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        self.stream.write_all(&frame).unwrap();
+    }
+
+    // read a frame, returning its opcode and payload.
+    fn read_frame(&mut self) -> (u8, Vec<u8>) {
+        let mut header = [0; 2];
+        self.stream.read_exact(&mut header).unwrap();
+        let length = match header[1] {
+            126 => {
+                let mut length = [0; 2];
+                self.stream.read_exact(&mut length).unwrap();
+                u16::from_be_bytes(length) as usize
+            }
+            length => length as usize,
+        };
+        let mut payload = vec![0; length];
+        self.stream.read_exact(&mut payload).unwrap();
+        (header[0] & 0x0F, payload)
+    }
+
+    fn read_text(&mut self) -> String {
+        let (opcode, payload) = self.read_frame();
+        assert_eq!(opcode, 1, "not a text frame");
+        String::from_utf8(payload).unwrap()
+    }
+
+    // skip the text frames until the close frame, returning its code, and
+    // check that nothing follows it.
+    fn read_close(&mut self) -> u16 {
+        loop {
+            let (opcode, payload) = self.read_frame();
+            if opcode == 8 {
+                let mut rest = Vec::new();
+                self.stream.read_to_end(&mut rest).unwrap();
+                assert!(rest.is_empty(), "data after the close frame: {:?}", rest);
+                return u16::from_be_bytes([payload[0], payload[1]]);
+            }
+        }
+    }
+
+    fn read_until(&mut self, matches: impl Fn(&str) -> bool) -> String {
+        loop {
+            let text = self.read_text();
+            if matches(&text) {
+                return text;
+            }
+        }
+    }
+}
+
+fn start_gateway() -> (SocketAddr, SocketAddr) {
+    let websocket = free_address();
+    let address = start_server_with(ServerConfig {
+        websocket_address: Some(websocket),
+        ..Default::default()
+    });
+    (address, websocket)
+}
+
+#[test]
+fn browser_and_terminal_users_chat_together() {
+    let (address, websocket) = start_gateway();
+    let mut alice = TestClient::join(address, "alice");
+
+    let mut browser = BrowserClient::connect(websocket);
+    browser.read_until(|text| text.starts_with(">>> use JOIN"));
+    browser.send("JOIN bob");
+    browser.read_until(|text| text.starts_with(">>> welcome to the chat, bob!"));
+    alice.expect_line("bob has joined the chat");
+
+    browser.send("hello from the browser");
+    alice.expect_line("[bob] hello from the browser");
+    alice.send("hello from the terminal");
+    assert_eq!(browser.read_text(), "[alice] hello from the terminal");
+
+    alice.send("WHO");
+    assert_eq!(alice.read_response("WHO").len(), 2);
+}
+
+#[test]
+fn leave_closes_the_websocket() {
+    let (address, websocket) = start_gateway();
+    let mut alice = TestClient::join(address, "alice");
+    let mut browser = BrowserClient::connect(websocket);
+    browser.send("JOIN bob");
+    alice.expect_line("bob has joined the chat");
+
+    browser.send("LEAVE");
+    alice.expect_line("bob has left the chat");
+    assert_eq!(browser.read_close(), 1000);
+}
+
+#[test]
+fn the_browser_closing_gets_a_single_close() {
+    let (_, websocket) = start_gateway();
+    let mut browser = BrowserClient::connect(websocket);
+    browser.read_until(|text| text.starts_with(">>> use JOIN"));
+
+    browser.send_frame(true, 8, &1000u16.to_be_bytes());
+    assert_eq!(browser.read_close(), 1000);
+}
+
+#[test]
+fn fragmented_messages_are_joined() {
+    let (address, websocket) = start_gateway();
+    let mut alice = TestClient::join(address, "alice");
+    let mut browser = BrowserClient::connect(websocket);
+    browser.send("JOIN bob");
+    alice.expect_line("bob has joined the chat");
+
+    browser.send_frame(false, 1, b"hello ");
+    browser.send_frame(true, 9, b"ping"); // control frames can come between
+    browser.send_frame(true, 0, b"there");
+    alice.expect_line("[bob] hello there");
+}
+
+#[test]
+fn frames_out_of_order_close_the_websocket() {
+    let (_, websocket) = start_gateway();
+
+    // a continuation without a message started
+    let mut browser = BrowserClient::connect(websocket);
+    browser.send_frame(true, 0, b"hello");
+    assert_eq!(browser.read_close(), 1002);
+
+    // a new message before the last one finished
+    let mut browser = BrowserClient::connect(websocket);
+    browser.send_frame(false, 1, b"hello");
+    browser.send_frame(true, 1, b"there");
+    assert_eq!(browser.read_close(), 1002);
+
+    // a fragmented ping
+    let mut browser = BrowserClient::connect(websocket);
+    browser.send_frame(false, 9, b"ping");
+    assert_eq!(browser.read_close(), 1002);
+}
+
+#[test]
+fn other_requests_are_refused() {
+    let (_, websocket) = start_gateway();
+    let mut stream = TcpStream::connect(websocket).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status).unwrap();
+    assert!(status.starts_with("HTTP/1.1 400 "), "{}", status);
+}